@@ -1,3 +1,4 @@
 import * as tprime from "./tprime";
+import { memory } from "./tprime_bg";
 
-window.startApplication(tprime.Application);
+window.startApplication(tprime.Application, memory);
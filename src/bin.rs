@@ -2,18 +2,621 @@
 #![feature(rust_2018_preview, try_from)]
 #![warn(missing_docs)]
 
+use std::{
+    collections::BTreeMap,
+    collections::HashMap,
+    env, fmt,
+    fs::File,
+    io::{self, BufWriter},
+    path::{Path, PathBuf},
+    process,
+    str::FromStr,
+    time::Instant,
+};
+
 use env_logger;
 
 mod mods;
-use self::mods::pathfinding;
+use self::mods::animation::{self, GifWriter};
+use self::mods::drawing;
+use self::mods::hpa::Hierarchy;
+use self::mods::jps::JumpPointSearch;
+use self::mods::map::Map;
+use self::mods::mapfile::{self, MapFile, MapFileError};
+use self::mods::output::Output;
+use self::mods::pathfinding::{AStarPathfinder, PathResult, Position, SearchStrategy};
+use self::mods::raster::Raster;
+use self::mods::svg::render_svg;
+use self::mods::topology::Geometry;
+
+/// The size of each cell in images, in pixels, unless it's given.
+const DEFAULT_SCALE: f64 = 16.;
+/// How long each frame of an animation is shown, in hundredths of a second.
+const FRAME_DELAY: u16 = 4;
+/// How long the finished search is shown before an animation loops.
+const FINAL_DELAY: u16 = 200;
+
+const USAGE: &str = "\
+usage:
+    tprime solve <map> [--from x,y] [--to x,y] [--algo astar] [--grid square8]
+    tprime render <map> [--from x,y] [--to x,y] [--algo astar] [--grid square8]
+                        [--svg out.svg] [--png out.png] [--scale 16]
+    tprime animate <map> [--from x,y] [--to x,y] [--algo astar] [--grid square8]
+                         [--gif out.gif] [--frames dir] [--every 1] [--scale 16]
+    tprime bench <scen> [--algo astar]
+
+Maps ending in .map are read in the Moving AI format, and anything else as
+ASCII, where S and G mark the default origin and target. Rendering prints the
+map as ASCII, or draws it as an SVG or PNG with --svg or --png, along with
+everything the search explored if it's one of the general searches.
+Animating records one of the general searches a frame for every so many
+steps, as a looping GIF or as numbered PNGs in a directory.
+
+algorithms: astar, dijkstra, bfs, dfs, greedy, hpa (square grids only),
+            jps, jps+ (8-way uniform-cost maps only)
+grids: square4, square8, hex";
 
 fn main() {
     env_logger::init();
 
-    let mut pathfinder = pathfinding::AStarPathfinder::default();
-    let path = pathfinder.get_path();
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Err(error) = run(&args) {
+        eprintln!("error: {}", error);
+        if let CliError::Usage(_) = error {
+            eprintln!("\n{}", USAGE);
+        }
+        process::exit(error.exit_code());
+    }
+}
+
+/// Everything that can go wrong running a command.
+#[derive(Debug)]
+enum CliError {
+    /// The arguments didn't make sense.
+    Usage(String),
+    /// A map or scenario file couldn't be read.
+    File(PathBuf, MapFileError),
+    /// A scenario gave a different size for its map than the map has.
+    WrongSize(PathBuf, Position, Position),
+    /// An origin or target wasn't given, either as an argument or in the map.
+    MissingEndpoint(&'static str),
+    /// An origin or target is outside of the map.
+    OutOfBounds(&'static str, Position, Position),
+    /// An origin or target is on impassable terrain.
+    Impassable(&'static str, Position),
+    /// The algorithm can't search this kind of map.
+    Unsupported(Algorithm),
+    /// The search finished without reaching the target.
+    NoPath(Position, Position),
+    /// An image couldn't be written.
+    Write(PathBuf, io::Error),
+}
+
+impl CliError {
+    fn exit_code(&self) -> i32 {
+        match self {
+            CliError::Usage(_) => 2,
+            _ => 1,
+        }
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::Usage(message) => write!(f, "{}", message),
+            CliError::File(path, error) => write!(f, "couldn't read {}: {}", path.display(), error),
+            CliError::WrongSize(path, stated, actual) => write!(
+                f,
+                "a scenario says {} is {}×{}, but it's {}×{}",
+                path.display(),
+                stated.0,
+                stated.1,
+                actual.0,
+                actual.1
+            ),
+            CliError::MissingEndpoint(name) => write!(
+                f,
+                "no {} given; pass --{} x,y or mark it in an ASCII map",
+                name,
+                if *name == "origin" { "from" } else { "to" }
+            ),
+            CliError::OutOfBounds(name, position, size) => write!(
+                f,
+                "{} {:?} is outside the {}×{} map",
+                name, position, size.0, size.1
+            ),
+            CliError::Impassable(name, position) => {
+                write!(f, "{} {:?} is on impassable terrain", name, position)
+            }
+            CliError::Unsupported(algorithm) => write!(
+                f,
+                "{} can't search this map, because it needs {}",
+                algorithm,
+                match algorithm {
+                    Algorithm::JumpPoint { .. } => {
+                        "an 8-way grid where every passable cell costs the same"
+                    }
+                    _ => "a square grid",
+                }
+            ),
+            CliError::NoPath(origin, target) => {
+                write!(f, "no path from {:?} to {:?}", origin, target)
+            }
+            CliError::Write(path, error) => {
+                write!(f, "couldn't write {}: {}", path.display(), error)
+            }
+        }
+    }
+}
+
+/// The pathfinding algorithm to run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Algorithm {
+    /// The general pathfinder, exploring in the given order.
+    Search(SearchStrategy),
+    /// Jump point search, optionally with JPS+ preprocessing.
+    JumpPoint { preprocess: bool },
+    /// Hierarchical search over clusters of the given size.
+    Hierarchical { cluster_size: usize },
+}
 
-    println!("AStarPathfinder::default().find_path == {:?}", path);
+impl Default for Algorithm {
+    fn default() -> Self {
+        Algorithm::Search(SearchStrategy::default())
+    }
+}
+
+impl FromStr for Algorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "jps" => Ok(Algorithm::JumpPoint { preprocess: false }),
+            "jps+" => Ok(Algorithm::JumpPoint { preprocess: true }),
+            "hpa" => Ok(Algorithm::Hierarchical { cluster_size: 16 }),
+            _ => s.parse().map(Algorithm::Search),
+        }
+    }
+}
+
+impl fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Algorithm::Search(strategy) => write!(f, "{}", strategy),
+            Algorithm::JumpPoint { preprocess: false } => write!(f, "JPS"),
+            Algorithm::JumpPoint { preprocess: true } => write!(f, "JPS+"),
+            Algorithm::Hierarchical { cluster_size } => {
+                write!(f, "HPA* with {}×{} clusters", cluster_size, cluster_size)
+            }
+        }
+    }
+}
+
+/// A prepared search over one map, which can answer any number of queries.
+enum Searcher {
+    Search(AStarPathfinder),
+    JumpPoint(JumpPointSearch),
+    Hierarchical(Hierarchy),
+}
+
+impl Searcher {
+    fn new(map: Map, algorithm: Algorithm) -> Result<Self, CliError> {
+        match algorithm {
+            Algorithm::Search(strategy) => Ok(Searcher::Search(AStarPathfinder::new(
+                map,
+                (0, 0),
+                (0, 0),
+                strategy,
+            ))),
+            Algorithm::JumpPoint { preprocess } => JumpPointSearch::new(map, preprocess)
+                .map(Searcher::JumpPoint)
+                .ok_or(CliError::Unsupported(algorithm)),
+            Algorithm::Hierarchical { cluster_size } => Hierarchy::new(map, cluster_size)
+                .map(Searcher::Hierarchical)
+                .ok_or(CliError::Unsupported(algorithm)),
+        }
+    }
+
+    fn find_path(&mut self, origin: Position, target: Position) -> PathResult {
+        match self {
+            Searcher::Search(pathfinder) => pathfinder.find_path(origin, target),
+            Searcher::JumpPoint(search) => search.find_path(origin, target),
+            Searcher::Hierarchical(hierarchy) => hierarchy.find_path(origin, target),
+        }
+    }
+}
+
+/// The parsed command-line arguments.
+#[derive(Debug, Default)]
+struct Options {
+    command: String,
+    path: PathBuf,
+    from: Option<Position>,
+    to: Option<Position>,
+    algorithm: Algorithm,
+    geometry: Option<Geometry>,
+    svg: Option<PathBuf>,
+    png: Option<PathBuf>,
+    gif: Option<PathBuf>,
+    /// The directory to save each frame of an animation in.
+    frames: Option<PathBuf>,
+    /// The number of steps between each frame of an animation.
+    every: Option<usize>,
+    /// The size of each cell in images, in pixels.
+    scale: Option<f64>,
+}
+
+impl Options {
+    fn parse(args: &[String]) -> Result<Self, CliError> {
+        let mut args = args.iter();
+        let mut options = Options::default();
+        let mut positional = Vec::new();
+
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| CliError::Usage(format!("{} needs a value", arg)))
+            };
+            match arg.as_str() {
+                "--from" => options.from = Some(parse_position(value()?)?),
+                "--to" => options.to = Some(parse_position(value()?)?),
+                "--algo" => options.algorithm = value()?.parse().map_err(CliError::Usage)?,
+                "--grid" => options.geometry = Some(value()?.parse().map_err(CliError::Usage)?),
+                "--svg" => options.svg = Some(PathBuf::from(value()?)),
+                "--png" => options.png = Some(PathBuf::from(value()?)),
+                "--scale" => options.scale = Some(parse_scale(value()?)?),
+                "--gif" => options.gif = Some(PathBuf::from(value()?)),
+                "--frames" => options.frames = Some(PathBuf::from(value()?)),
+                "--every" => options.every = Some(parse_every(value()?)?),
+                "-h" | "--help" => return Err(CliError::Usage("showing help".to_string())),
+                flag if flag.starts_with("--") => {
+                    return Err(CliError::Usage(format!("unknown option {}", flag)))
+                }
+                _ => positional.push(arg.clone()),
+            }
+        }
+
+        match positional.as_slice() {
+            [command, path] => {
+                options.command = command.clone();
+                options.path = PathBuf::from(path);
+                Ok(options)
+            }
+            [] => Err(CliError::Usage("no command given".to_string())),
+            _ => Err(CliError::Usage(format!(
+                "expected a command and a file, found {:?}",
+                positional
+            ))),
+        }
+    }
+}
+
+/// Parses a position written as `x,y`.
+fn parse_position(s: &str) -> Result<Position, CliError> {
+    let invalid = || CliError::Usage(format!("invalid position {:?}, expected x,y", s));
+    let mut parts = s.split(',');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(x), Some(y), None) => Ok((
+            x.trim().parse().map_err(|_| invalid())?,
+            y.trim().parse().map_err(|_| invalid())?,
+        )),
+        _ => Err(invalid()),
+    }
+}
+
+/// Parses the size of each cell in images, which has to be positive.
+fn parse_scale(s: &str) -> Result<f64, CliError> {
+    match s.parse::<f64>() {
+        Ok(scale) if scale > 0. && scale.is_finite() => Ok(scale),
+        _ => Err(CliError::Usage(format!(
+            "invalid scale {:?}, expected a positive number",
+            s
+        ))),
+    }
+}
+
+/// Parses the number of steps between frames, which has to be at least one.
+fn parse_every(s: &str) -> Result<usize, CliError> {
+    match s.parse::<usize>() {
+        Ok(every) if every > 0 => Ok(every),
+        _ => Err(CliError::Usage(format!(
+            "invalid step count {:?}, expected a positive whole number",
+            s
+        ))),
+    }
+}
+
+fn run(args: &[String]) -> Result<(), CliError> {
+    let options = Options::parse(args)?;
+    match options.command.as_str() {
+        "solve" => solve(&options),
+        "render" => render(&options),
+        "animate" => animate(&options),
+        "bench" => bench(&options),
+        command => Err(CliError::Usage(format!("unknown command {:?}", command))),
+    }
+}
+
+/// Loads the map named by the options, applying any endpoints and grid they override.
+fn load_map(options: &Options) -> Result<MapFile, CliError> {
+    let mut file = mapfile::load(&options.path)
+        .map_err(|error| CliError::File(options.path.clone(), error))?;
+    if let Some(geometry) = options.geometry {
+        file.map.set_geometry(geometry);
+    }
+    file.origin = options.from.or(file.origin);
+    file.target = options.to.or(file.target);
+    Ok(file)
+}
+
+/// Checks that an endpoint was given and can be stood on.
+fn check_endpoint(
+    map: &Map,
+    name: &'static str,
+    position: Option<Position>,
+) -> Result<Position, CliError> {
+    let position = position.ok_or(CliError::MissingEndpoint(name))?;
+    if !map.contains(position) {
+        return Err(CliError::OutOfBounds(name, position, map.len()));
+    }
+    if !map.passable(position) {
+        return Err(CliError::Impassable(name, position));
+    }
+    Ok(position)
+}
+
+/// Finds a path between the endpoints of a map file, along with the searcher
+/// that found it, so that what it explored can be drawn.
+fn search(file: &MapFile, algorithm: Algorithm) -> Result<(Searcher, PathResult), CliError> {
+    let origin = check_endpoint(&file.map, "origin", file.origin)?;
+    let target = check_endpoint(&file.map, "target", file.target)?;
+    let mut searcher = Searcher::new(file.map.clone(), algorithm)?;
+    let result = searcher.find_path(origin, target);
+    if result.found() {
+        Ok((searcher, result))
+    } else {
+        Err(CliError::NoPath(origin, target))
+    }
+}
+
+/// Finds a path between the endpoints of a map file.
+fn find_path(file: &MapFile, algorithm: Algorithm) -> Result<PathResult, CliError> {
+    search(file, algorithm).map(|(_, result)| result)
+}
+
+fn solve(options: &Options) -> Result<(), CliError> {
+    let file = load_map(options)?;
+    let result = find_path(&file, options.algorithm)?;
+
+    println!(
+        "{} found a path of cost {} in {} steps, expanding {} cells with at most {} in the frontier:",
+        options.algorithm,
+        result.cost,
+        result.path.len() - 1,
+        result.nodes_expanded,
+        result.frontier_peak
+    );
+    let positions: Vec<String> = result
+        .path
+        .iter()
+        .map(|(x, y)| format!("{},{}", x, y))
+        .collect();
+    println!("{}", positions.join(" "));
+
+    Ok(())
+}
+
+fn render(options: &Options) -> Result<(), CliError> {
+    let file = load_map(options)?;
+    let searched = if file.origin.is_some() || file.target.is_some() {
+        Some(search(&file, options.algorithm)?)
+    } else {
+        None
+    };
+
+    if options.svg.is_some() || options.png.is_some() {
+        let output = draw(
+            &file,
+            searched.as_ref(),
+            options.scale.unwrap_or(DEFAULT_SCALE),
+        );
+        if let Some(path) = &options.svg {
+            std::fs::write(path, render_svg(&output))
+                .map_err(|error| CliError::Write(path.clone(), error))?;
+        }
+        if let Some(path) = &options.png {
+            Raster::render(&output)
+                .save_png(path)
+                .map_err(|error| CliError::Write(path.clone(), error))?;
+        }
+        return Ok(());
+    }
+
+    let path = searched.map(|(_, result)| result.path).unwrap_or_default();
+    let text = mapfile::write_ascii(&file.map, file.origin, file.target);
+    let mut rows: Vec<Vec<char>> = text.lines().map(|line| line.chars().collect()).collect();
+    for (x, y) in path.iter().skip(1).take(path.len().saturating_sub(2)) {
+        rows[*y][*x] = '*';
+    }
+    for row in rows {
+        println!("{}", row.into_iter().collect::<String>());
+    }
+
+    Ok(())
+}
+
+/// Draws a map, and the path found across it along with the search tree of
+/// the general searches, the same way the canvas does.
+fn draw(file: &MapFile, searched: Option<&(Searcher, PathResult)>, scale: f64) -> Output {
+    let map = &file.map;
+    let mut output = drawing::frame(map, scale);
+    if let Some((searcher, result)) = searched {
+        if let Searcher::Search(pathfinder) = searcher {
+            drawing::draw_search(
+                map,
+                pathfinder.search(),
+                drawing::FORWARD_SEARCH_COLORS,
+                scale,
+                &mut output,
+            );
+        }
+        drawing::draw_path(map, &result.path, scale, &mut output);
+    }
+    if let Some(origin) = file.origin {
+        drawing::draw_label(map, origin, "S", scale, &mut output);
+    }
+    if let Some(target) = file.target {
+        drawing::draw_label(map, target, "G", scale, &mut output);
+    }
+    output
+}
+
+fn animate(options: &Options) -> Result<(), CliError> {
+    let strategy = match options.algorithm {
+        Algorithm::Search(strategy) => strategy,
+        algorithm => {
+            return Err(CliError::Usage(format!(
+                "{} can't be animated; only the general searches run step by step",
+                algorithm
+            )))
+        }
+    };
+    if options.gif.is_none() && options.frames.is_none() {
+        return Err(CliError::Usage(
+            "nothing to animate to; pass --gif or --frames".to_string(),
+        ));
+    }
+    let file = load_map(options)?;
+    let origin = check_endpoint(&file.map, "origin", file.origin)?;
+    let target = check_endpoint(&file.map, "target", file.target)?;
+
+    let mut pathfinder = AStarPathfinder::new(file.map, origin, target, strategy);
+    let mut gif: Option<GifWriter<BufWriter<File>>> = None;
+    let mut count = 0;
+    let result = animation::record_search(
+        &mut pathfinder,
+        options.every.unwrap_or(1),
+        options.scale.unwrap_or(DEFAULT_SCALE),
+        |raster, finished| {
+            if let Some(path) = &options.gif {
+                let write_error = |error| CliError::Write(path.clone(), error);
+                if gif.is_none() {
+                    let file = File::create(path).map_err(write_error)?;
+                    let writer =
+                        GifWriter::new(BufWriter::new(file), raster.width(), raster.height());
+                    gif = Some(writer.map_err(write_error)?);
+                }
+                let delay = if finished { FINAL_DELAY } else { FRAME_DELAY };
+                if let Some(gif) = &mut gif {
+                    gif.write(raster, delay).map_err(write_error)?;
+                }
+            }
+            if let Some(directory) = &options.frames {
+                animation::save_frame(directory, count, raster)
+                    .map_err(|error| CliError::Write(directory.clone(), error))?;
+            }
+            count += 1;
+            Ok(())
+        },
+    )?;
+
+    println!(
+        "{} expanded {} cells over {} frames",
+        options.algorithm, result.nodes_expanded, count
+    );
+    if result.found() {
+        Ok(())
+    } else {
+        Err(CliError::NoPath(origin, target))
+    }
+}
+
+/// The results for one bucket of a scenario file.
+#[derive(Default)]
+struct BucketResults {
+    problems: usize,
+    solved: usize,
+    nodes_expanded: usize,
+    seconds: f64,
+    worst_error: f64,
+}
+
+fn bench(options: &Options) -> Result<(), CliError> {
+    let scenarios = mapfile::load_scenarios(&options.path)
+        .map_err(|error| CliError::File(options.path.clone(), error))?;
+    let directory = options.path.parent().unwrap_or_else(|| Path::new(""));
+
+    let mut searchers: HashMap<String, (Map, Searcher)> = HashMap::new();
+    let mut buckets: BTreeMap<usize, BucketResults> = BTreeMap::new();
+
+    for scenario in scenarios.iter() {
+        let path = directory.join(&scenario.map);
+        if !searchers.contains_key(&scenario.map) {
+            let file = mapfile::load(&path).map_err(|error| CliError::File(path.clone(), error))?;
+            let searcher = Searcher::new(file.map.clone(), options.algorithm)?;
+            searchers.insert(scenario.map.clone(), (file.map, searcher));
+        }
+        let (map, searcher) = searchers.get_mut(&scenario.map).unwrap();
+
+        // Check each problem before it's run, so a bad line in the scenario
+        // is reported rather than taking the search out of bounds.
+        if scenario.size != map.len() {
+            return Err(CliError::WrongSize(path, scenario.size, map.len()));
+        }
+        check_endpoint(map, "origin", Some(scenario.origin))?;
+        check_endpoint(map, "target", Some(scenario.target))?;
+
+        let start = Instant::now();
+        let result = searcher.find_path(scenario.origin, scenario.target);
+        let elapsed = start.elapsed();
+
+        let results = buckets
+            .entry(scenario.bucket)
+            .or_insert_with(Default::default);
+        results.problems += 1;
+        results.seconds += elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) * 1e-9;
+
+        results.nodes_expanded += result.nodes_expanded;
+
+        if result.found() {
+            results.solved += 1;
+            let length = path_length(&result.path);
+            if scenario.optimal_length > 0. {
+                let error = (length - scenario.optimal_length).abs() / scenario.optimal_length;
+                results.worst_error = results.worst_error.max(error);
+            }
+        }
+    }
+
+    println!("{}", options.algorithm);
+    println!("bucket  problems  solved  mean expanded  total ms  worst length error");
+    for (bucket, results) in buckets.iter() {
+        println!(
+            "{:>6}  {:>8}  {:>6}  {:>13}  {:>8.1}  {:>17.2}%",
+            bucket,
+            results.problems,
+            results.solved,
+            results.nodes_expanded / results.problems,
+            results.seconds * 1000.,
+            results.worst_error * 100.
+        );
+    }
+
+    Ok(())
+}
+
+/// The length of a path on an eight-way grid, counting diagonal steps as √2
+/// like scenario files do. Consecutive positions can be further apart than
+/// neighbours, as long as they're in a straight or diagonal line, or a mix.
+fn path_length(path: &[Position]) -> f64 {
+    path.windows(2)
+        .map(|pair| {
+            let dx = (pair[0].0 as f64 - pair[1].0 as f64).abs();
+            let dy = (pair[0].1 as f64 - pair[1].1 as f64).abs();
+            let diagonal = dx.min(dy);
+            (dx.max(dy) - diagonal) + diagonal * std::f64::consts::SQRT_2
+        }).sum()
 }
 
 #[test]
@@ -21,3 +624,211 @@ fn main() {
 fn test_main() {
     main();
 }
+
+#[cfg(test)]
+fn args(s: &str) -> Vec<String> {
+    s.split_whitespace().map(String::from).collect()
+}
+
+#[test]
+fn test_parse_options() {
+    let options = Options::parse(&args("solve x.map --from 1,2 --to 3,4 --algo bfs")).unwrap();
+    assert_eq!(options.command, "solve");
+    assert_eq!(options.from, Some((1, 2)));
+    assert_eq!(options.to, Some((3, 4)));
+    assert_eq!(
+        options.algorithm,
+        Algorithm::Search(SearchStrategy::BreadthFirst)
+    );
+    let options = Options::parse(&args("bench x.scen --algo jps+")).unwrap();
+    assert_eq!(options.algorithm, Algorithm::JumpPoint { preprocess: true });
+    let options = Options::parse(&args("render x.map --png x.png --scale 8")).unwrap();
+    assert_eq!(options.png, Some(PathBuf::from("x.png")));
+    assert_eq!(options.scale, Some(8.));
+    let options = Options::parse(&args("animate x.map --gif x.gif --every 4")).unwrap();
+    assert_eq!(options.gif, Some(PathBuf::from("x.gif")));
+    assert_eq!(options.every, Some(4));
+
+    for bad in [
+        "solve x.map --from 1",
+        "solve x.map --algo magic",
+        "solve x.map --to",
+        "solve",
+        "solve x.map --colour",
+        "render x.map --scale 0",
+        "render x.map --scale big",
+        "animate x.map --every 0",
+    ]
+    .iter()
+    {
+        match Options::parse(&args(bad)) {
+            Err(CliError::Usage(_)) => {}
+            _ => panic!("{:?} should be a usage error", bad),
+        }
+    }
+}
+
+#[test]
+fn test_solve_errors() {
+    let path = env::temp_dir().join(format!("tprime-cli-{}.txt", process::id()));
+    std::fs::write(&path, "S.#\n.##\n##G\n").unwrap();
+    let path = path.to_str().unwrap();
+
+    match run(&args(&format!("frobnicate {}", path))) {
+        Err(CliError::Usage(_)) => {}
+        _ => panic!("unknown commands should be rejected"),
+    }
+    match run(&args("solve /nonexistent/tprime.map")) {
+        Err(CliError::File(_, MapFileError::Io(_))) => {}
+        _ => panic!("missing files should be reported"),
+    }
+    match run(&args(&format!("solve {} --from 7,0", path))) {
+        Err(CliError::OutOfBounds("origin", (7, 0), (3, 3))) => {}
+        _ => panic!("out-of-bounds points should be rejected"),
+    }
+    match run(&args(&format!("solve {} --to 2,0", path))) {
+        Err(CliError::Impassable("target", (2, 0))) => {}
+        _ => panic!("impassable points should be rejected"),
+    }
+    match run(&args(&format!("solve {}", path))) {
+        Err(CliError::NoPath((0, 0), (2, 2))) => {}
+        _ => panic!("unreachable targets should be reported"),
+    }
+    assert!(run(&args(&format!("solve {} --to 0,1", path))).is_ok());
+    assert!(run(&args(&format!("render {} --grid hex --to 1,0", path))).is_ok());
+    assert!(run(&args(&format!("solve {} --algo jps+ --to 0,1", path))).is_ok());
+    match run(&args(&format!("solve {} --algo jps --grid square4", path))) {
+        Err(CliError::Unsupported(_)) => {}
+        _ => panic!("maps that can't be jump point searched should be rejected"),
+    }
+    assert!(run(&args(&format!("solve {} --algo hpa --to 0,1", path))).is_ok());
+
+    std::fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_bench_errors() {
+    let dir = env::temp_dir();
+    let map = format!("tprime-bench-{}.map", process::id());
+    let scen = dir.join(format!("tprime-bench-{}.scen", process::id()));
+    mapfile::save(
+        &dir.join(&map),
+        &mapfile::read_ascii("..#\n.#.\n...\n").unwrap(),
+    )
+    .unwrap();
+    let bench = |line: &str| {
+        let text = format!("version 1\n0\t{}\t{}\t2.0\n", map, line);
+        std::fs::write(&scen, text).unwrap();
+        run(&args(&format!("bench {}", scen.display())))
+    };
+
+    assert!(bench("3\t3\t0\t0\t2\t2").is_ok());
+    match bench("3\t3\t0\t0\t5\t2") {
+        Err(CliError::OutOfBounds("target", (5, 2), (3, 3))) => {}
+        _ => panic!("out-of-bounds scenarios should be rejected"),
+    }
+    match bench("3\t3\t1\t1\t2\t2") {
+        Err(CliError::Impassable("origin", (1, 1))) => {}
+        _ => panic!("impassable scenarios should be rejected"),
+    }
+    match bench("4\t3\t0\t0\t2\t2") {
+        Err(CliError::WrongSize(_, (4, 3), (3, 3))) => {}
+        _ => panic!("scenarios for a different size of map should be rejected"),
+    }
+
+    std::fs::remove_file(dir.join(&map)).unwrap();
+    std::fs::remove_file(&scen).unwrap();
+}
+
+#[test]
+fn test_path_length() {
+    assert_eq!(path_length(&[]), 0.);
+    assert_eq!(path_length(&[(2, 2), (2, 5), (0, 5)]), 5.);
+    let length = path_length(&[(0, 0), (1, 1), (4, 3)]);
+    assert!((length - (1. + 3. * std::f64::consts::SQRT_2)).abs() < 1e-9);
+}
+
+#[test]
+fn test_render_images() {
+    let dir = env::temp_dir();
+    let map = dir.join(format!("tprime-render-{}.txt", process::id()));
+    let svg = dir.join(format!("tprime-render-{}.svg", process::id()));
+    let png = dir.join(format!("tprime-render-{}.png", process::id()));
+    std::fs::write(&map, "S.#\n.#.\n..G\n").unwrap();
+    let command = format!(
+        "render {} --svg {} --png {} --scale 8",
+        map.display(),
+        svg.display(),
+        png.display()
+    );
+    run(&args(&command)).unwrap();
+
+    let text = std::fs::read_to_string(&svg).unwrap();
+    assert!(text.starts_with(r#"<svg xmlns="http://www.w3.org/2000/svg" width="24" height="24""#));
+    assert!(text.contains(">S</text>") && text.contains(">G</text>"));
+    let (info, _) = png::Decoder::new(std::fs::File::open(&png).unwrap())
+        .read_info()
+        .unwrap();
+    assert_eq!((info.width, info.height), (24, 24));
+
+    match run(&args(&format!(
+        "render {} --png /nonexistent/x.png",
+        map.display()
+    ))) {
+        Err(CliError::Write(_, _)) => {}
+        _ => panic!("unwritable images should be reported"),
+    }
+    for path in &[map, svg, png] {
+        std::fs::remove_file(path).unwrap();
+    }
+}
+
+#[test]
+fn test_animate() {
+    let dir = env::temp_dir();
+    let map = dir.join(format!("tprime-animate-{}.txt", process::id()));
+    let gif = dir.join(format!("tprime-animate-{}.gif", process::id()));
+    let frames = dir.join(format!("tprime-animate-{}", process::id()));
+    std::fs::write(&map, "S.#\n.#.\n..G\n").unwrap();
+    let command = format!(
+        "animate {} --gif {} --frames {} --every 2 --scale 8",
+        map.display(),
+        gif.display(),
+        frames.display()
+    );
+    run(&args(&command)).unwrap();
+
+    let mut decoder = gif::Decoder::new(File::open(&gif).unwrap())
+        .read_info()
+        .unwrap();
+    let mut delays = Vec::new();
+    while let Some(frame) = decoder.read_next_frame().unwrap() {
+        delays.push(frame.delay);
+    }
+    assert_eq!(delays.last(), Some(&FINAL_DELAY));
+    assert!(delays.len() > 1);
+    let mut names: Vec<String> = std::fs::read_dir(&frames)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect();
+    names.sort();
+    assert_eq!(names.len(), delays.len());
+    assert_eq!(names[0], "frame-0000.png");
+
+    match run(&args(&format!(
+        "animate {} --algo jps --gif {}",
+        map.display(),
+        gif.display()
+    ))) {
+        Err(CliError::Usage(_)) => {}
+        _ => panic!("searches that can't be stepped shouldn't be animated"),
+    }
+    match run(&args(&format!("animate {}", map.display()))) {
+        Err(CliError::Usage(_)) => {}
+        _ => panic!("animating to nowhere should be rejected"),
+    }
+    std::fs::remove_dir_all(frames).unwrap();
+    for path in &[map, gif] {
+        std::fs::remove_file(path).unwrap();
+    }
+}
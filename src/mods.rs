@@ -1 +1,19 @@
+pub mod animation;
+pub mod anyangle;
+pub mod components;
+pub mod cooperative;
+pub mod drawing;
+pub mod dstar;
+pub mod flowfield;
+pub mod generators;
+pub mod hpa;
+pub mod jps;
+pub mod map;
+pub mod mapfile;
+pub mod output;
 pub mod pathfinding;
+pub mod raster;
+pub mod renderbuffer;
+pub mod svg;
+pub mod terrain;
+pub mod topology;
@@ -0,0 +1,208 @@
+//! Recording searches step by step, as animated GIFs or numbered PNGs drawn
+//! in the same colours as the canvas.
+
+use std::{fs, io, io::Write, path::Path};
+
+use gif::SetParameter;
+
+use super::drawing;
+use super::output::Output;
+use super::pathfinding::{AStarPathfinder, PathResult};
+use super::raster::Raster;
+
+/// The colour behind the map, the same as the page the canvas is on.
+pub const BACKGROUND: &str = "rgb(24, 24, 24)";
+
+/// Draws a search as it stands, the way the canvas does: the search tree
+/// from each end, the best path so far and the labels at each end.
+pub fn search_frame(pathfinder: &AStarPathfinder, scale: f64) -> Output {
+    let mut output = drawing::frame(pathfinder.map(), scale);
+    draw_progress(pathfinder, scale, &mut output);
+    output
+}
+
+/// Draws everything in a search frame over the terrain, which is all that
+/// changes as the search goes on.
+fn draw_progress(pathfinder: &AStarPathfinder, scale: f64, output: &mut Output) {
+    let map = pathfinder.map();
+    drawing::draw_search(
+        map,
+        pathfinder.search(),
+        drawing::FORWARD_SEARCH_COLORS,
+        scale,
+        output,
+    );
+    if let Some(search) = pathfinder.backward_search() {
+        drawing::draw_search(map, search, drawing::BACKWARD_SEARCH_COLORS, scale, output);
+    }
+    drawing::draw_path(map, &pathfinder.peek_path().path, scale, output);
+    drawing::draw_label(map, pathfinder.origin(), "S", scale, output);
+    for target in pathfinder.targets() {
+        drawing::draw_label(map, *target, "G", scale, output);
+    }
+}
+
+/// Runs a search to the end, handing `frame` an image of it before the
+/// first step, after every `every` steps and once it's finished, along with
+/// whether it's the last one. If `frame` fails, the search stops there.
+///
+/// The terrain is only drawn once, and each frame draws the search over a
+/// copy of it.
+pub fn record_search<F, E>(
+    pathfinder: &mut AStarPathfinder,
+    every: usize,
+    scale: f64,
+    mut frame: F,
+) -> Result<PathResult, E>
+where
+    F: FnMut(&Raster, bool) -> Result<(), E>,
+{
+    let terrain = drawing::frame(pathfinder.map(), scale);
+    let mut background = Raster::new(terrain.width as usize, terrain.height as usize);
+    background.fill(BACKGROUND);
+    background.draw(&terrain);
+    loop {
+        let mut output = Output::new(&drawing::LAYERS);
+        draw_progress(pathfinder, scale, &mut output);
+        let mut raster = background.clone();
+        raster.draw(&output);
+        let finished = !pathfinder.working();
+        frame(&raster, finished)?;
+        if finished {
+            return Ok(pathfinder.get_path());
+        }
+        pathfinder.step_n(every.max(1));
+    }
+}
+
+/// Saves a frame of a sequence as `frame-0000.png`, `frame-0001.png` and so
+/// on in a directory, so they sort in order.
+pub fn save_frame(directory: &Path, index: usize, raster: &Raster) -> io::Result<()> {
+    fs::create_dir_all(directory)?;
+    raster.save_png(&directory.join(format!("frame-{:04}.png", index)))
+}
+
+/// An animated GIF that loops forever, written a frame at a time.
+pub struct GifWriter<W: Write> {
+    encoder: gif::Encoder<W>,
+    size: (u16, u16),
+}
+
+impl<W: Write> GifWriter<W> {
+    /// Starts a GIF with room for frames of the given size, which has to
+    /// fit in the 65535 pixels a side that GIFs allow.
+    pub fn new(writer: W, width: usize, height: usize) -> io::Result<Self> {
+        let limit = usize::from(u16::max_value());
+        if width > limit || height > limit {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("a {}×{} image is too large for a GIF", width, height),
+            ));
+        }
+        let (width, height) = (width as u16, height as u16);
+        let mut encoder = gif::Encoder::new(writer, width, height, &[])?;
+        encoder.set(gif::Repeat::Infinite)?;
+        Ok(Self {
+            encoder,
+            size: (width, height),
+        })
+    }
+
+    /// Adds a frame, shown for `delay` hundredths of a second. Each frame is
+    /// reduced to its own palette of 256 colours, so images should be opaque
+    /// to keep the edges of shapes from being speckled.
+    pub fn write(&mut self, raster: &Raster, delay: u16) -> io::Result<()> {
+        assert_eq!(
+            (raster.width(), raster.height()),
+            (self.size.0 as usize, self.size.1 as usize)
+        );
+        let mut pixels = raster.to_rgba();
+        let mut frame = gif::Frame::from_rgba_speed(self.size.0, self.size.1, &mut pixels, 10);
+        frame.delay = delay;
+        self.encoder.write_frame(&frame)
+    }
+}
+
+#[cfg(test)]
+use super::{map::Map, pathfinding::SearchStrategy, terrain::Terrain, topology::Geometry};
+
+#[test]
+fn test_record_search() {
+    let mut map = Map::new(8, 5, Geometry::Square8);
+    for y in 0..4 {
+        map.set_terrain((4, y), Terrain::Wall);
+    }
+    let mut pathfinder = AStarPathfinder::new(map.clone(), (1, 1), (6, 1), SearchStrategy::AStar);
+    let mut frames = Vec::new();
+    let result = record_search(&mut pathfinder, 4, 8., |raster, finished| {
+        frames.push((raster.clone(), finished));
+        Ok::<(), ()>(())
+    }).unwrap();
+    assert!(result.found());
+    assert_eq!(result.nodes_expanded, {
+        let mut pathfinder = AStarPathfinder::new(map, (1, 1), (6, 1), SearchStrategy::AStar);
+        pathfinder.get_path().nodes_expanded
+    });
+
+    // Only the last frame is finished, and there's one frame for every four
+    // steps, with the first before any.
+    let finished: Vec<bool> = frames.iter().map(|(_, finished)| *finished).collect();
+    assert_eq!(finished.iter().filter(|finished| **finished).count(), 1);
+    assert_eq!(finished.last(), Some(&true));
+    assert!(frames.len() >= 3);
+    assert_ne!(frames[0].0, frames[1].0);
+
+    // The frames are opaque, with the walls and the finished path in the
+    // canvas's colours.
+    let (last, _) = &frames[frames.len() - 1];
+    assert_eq!((last.width(), last.height()), (64, 40));
+    assert!(last.to_rgba().chunks(4).all(|pixel| pixel[3] == 255));
+    assert_eq!(last.pixel(36, 4), [192, 0, 64, 255]);
+    assert_eq!(last.pixel(36, 36), [64, 192, 64, 255]);
+
+    // Drawing the search over the terrain drawn earlier gives the same image
+    // as drawing the whole frame at once.
+    let mut whole = Raster::new(64, 40);
+    whole.fill(BACKGROUND);
+    whole.draw(&search_frame(&pathfinder, 8.));
+    assert!(*last == whole);
+
+    let mut gif = Vec::new();
+    {
+        let mut writer = GifWriter::new(&mut gif, last.width(), last.height()).unwrap();
+        for (raster, finished) in &frames {
+            writer
+                .write(raster, if *finished { 200 } else { 5 }).unwrap();
+        }
+    }
+    let mut decoder = gif::Decoder::new(&gif[..]).read_info().unwrap();
+    let mut count = 0;
+    while let Some(frame) = decoder.read_next_frame().unwrap() {
+        assert_eq!((frame.width, frame.height), (64, 40));
+        count += 1;
+    }
+    assert_eq!(count, frames.len());
+}
+
+#[test]
+fn test_record_unreachable_search() {
+    let mut map = Map::new(8, 5, Geometry::Square8);
+    for y in 0..5 {
+        map.set_terrain((4, y), Terrain::Wall);
+    }
+    let mut pathfinder = AStarPathfinder::new(map, (1, 1), (6, 1), SearchStrategy::AStar);
+    let mut last = None;
+    let result = record_search(&mut pathfinder, 4, 8., |raster, _| {
+        last = Some(raster.clone());
+        Ok::<(), ()>(())
+    }).unwrap();
+    assert!(!result.found());
+    assert!(result.path.is_empty());
+
+    // The last frame has no path to draw, but the search is still there.
+    let last = last.unwrap();
+    let mut whole = Raster::new(64, 40);
+    whole.fill(BACKGROUND);
+    whole.draw(&search_frame(&pathfinder, 8.));
+    assert!(last == whole);
+}
@@ -0,0 +1,420 @@
+//! Any-angle paths, which run in straight lines between the centres of cells
+//! that can see each other instead of stepping from cell to cell: Theta* and
+//! Lazy Theta*, which search for them directly, and string pulling, which
+//! straightens a path that's already been found.
+//!
+//! A straight line between two cells costs its length times the most
+//! expensive terrain it passes through, so that cutting across a corner of a
+//! swamp costs as much as wading straight through it.
+
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+use super::map::Map;
+use super::pathfinding::{Distance, PathOutcome, PathResult, Position, SearchState};
+use super::topology::{Geometry, STEP_COST};
+#[cfg(test)]
+use super::{
+    generators,
+    pathfinding::{AStarPathfinder, SearchStrategy},
+    terrain::{Terrain, TerrainCosts},
+};
+
+/// The cells that a straight line between the centres of two cells passes
+/// through, or `None` if it leaves the map. Where it passes exactly through a
+/// corner, both cells beside the corner are included too, unless the map lets
+/// diagonal steps cut corners.
+fn cells_between(map: &Map, a: Position, b: Position) -> Option<Vec<Position>> {
+    let (ax, ay) = (a.0 as isize, a.1 as isize);
+    let (bx, by) = (b.0 as isize, b.1 as isize);
+    let mut cells = Vec::new();
+
+    if map.geometry() == Geometry::Hex {
+        // Sample the line at each hex it could cross, nudged slightly to
+        // either side so that lines along an edge catch the hexes on both.
+        let (dq, dr) = ((bx - ax) as f64, (by - ay) as f64);
+        let steps = map.topology().heuristic(a, b) / STEP_COST;
+        for i in 0..=steps {
+            let t = if steps == 0 {
+                0.
+            } else {
+                i as f64 / steps as f64
+            };
+            for nudge in [1e-6, -1e-6].iter() {
+                let q = ax as f64 + dq * t + nudge;
+                let r = ay as f64 + dr * t + nudge;
+                cells.push(hex_round(q, r));
+            }
+        }
+    } else {
+        // Walk the grid one cell boundary at a time, comparing how far along
+        // the line the next vertical and horizontal boundaries are.
+        let (dx, dy) = ((bx - ax).abs(), (by - ay).abs());
+        let (sx, sy) = ((bx - ax).signum(), (by - ay).signum());
+        let (mut x, mut y) = (ax, ay);
+        let mut error = dx - dy;
+        cells.push((x, y));
+        while (x, y) != (bx, by) {
+            if error > 0 {
+                x += sx;
+                error -= 2 * dy;
+            } else if error < 0 {
+                y += sy;
+                error += 2 * dx;
+            } else {
+                if !map.costs().cut_corners() {
+                    cells.push((x + sx, y));
+                    cells.push((x, y + sy));
+                }
+                x += sx;
+                y += sy;
+                error += 2 * (dx - dy);
+            }
+            cells.push((x, y));
+        }
+    }
+
+    cells
+        .into_iter()
+        .map(|(x, y)| {
+            if x >= 0 && y >= 0 && map.contains((x as Distance, y as Distance)) {
+                Some((x as Distance, y as Distance))
+            } else {
+                None
+            }
+        }).collect()
+}
+
+/// Rounds fractional axial hex coordinates to the hex containing them.
+fn hex_round(q: f64, r: f64) -> (isize, isize) {
+    let s = -q - r;
+    let (mut rq, mut rr, rs) = (q.round(), r.round(), s.round());
+    let (dq, dr, ds) = ((rq - q).abs(), (rr - r).abs(), (rs - s).abs());
+    if dq > dr && dq > ds {
+        rq = -rr - rs;
+    } else if dr > ds {
+        rr = -rq - rs;
+    }
+    (rq as isize, rr as isize)
+}
+
+/// The distance between the centres of two cells, in steps between neighbours.
+fn euclidean_distance(map: &Map, a: Position, b: Position) -> f64 {
+    let topology = map.topology();
+    let (ax, ay) = topology.cell_center(a, 1.);
+    let (bx, by) = topology.cell_center(b, 1.);
+    (ax - bx).hypot(ay - by)
+}
+
+/// The cost of a straight line between the centres of two cells, or `None`
+/// if it passes through anything impassable.
+pub fn segment_cost(map: &Map, a: Position, b: Position) -> Option<Distance> {
+    let mut cost = 0;
+    for position in cells_between(map, a, b)? {
+        cost = cost.max(map.costs().get(map.terrain()[position])?);
+    }
+    Some((euclidean_distance(map, a, b) * STEP_COST as f64).round() as Distance * cost)
+}
+
+/// Whether a straight line between the centres of two cells passes only
+/// through passable cells.
+pub fn line_of_sight(map: &Map, a: Position, b: Position) -> bool {
+    segment_cost(map, a, b).is_some()
+}
+
+/// Straightens a path by pulling it taut: from each waypoint, skips ahead to
+/// the furthest later cell on the path that can be seen from it. The result
+/// only keeps cells from the original path, in the same order.
+///
+/// This only checks that the shortcuts are passable, so a straightened path
+/// can cost more than the original if it cuts through expensive terrain.
+pub fn smooth_path(map: &Map, path: &[Position]) -> Vec<Position> {
+    let mut smoothed: Vec<Position> = path.iter().take(1).cloned().collect();
+    let mut anchor = 0;
+    while anchor + 1 < path.len() {
+        let next = (anchor + 1..path.len())
+            .rev()
+            .find(|i| *i == anchor + 1 || line_of_sight(map, path[anchor], path[*i]))
+            .unwrap();
+        smoothed.push(path[next]);
+        anchor = next;
+    }
+    smoothed
+}
+
+/// Searches for any-angle paths with Theta*, which lets each cell take its
+/// parent's parent as its own whenever it can see it.
+pub struct AnyAngleSearch {
+    /// The grid we're searching.
+    map: Map,
+    /// Whether this is Lazy Theta*, which assumes each cell can see its
+    /// parent's parent and only checks when the cell is expanded.
+    lazy: bool,
+    /// The cells expanded by the last search, and their parents.
+    search: SearchState,
+}
+
+impl AnyAngleSearch {
+    pub fn new(map: Map, lazy: bool) -> Self {
+        let search = SearchState::new(map.len());
+        Self { map, lazy, search }
+    }
+
+    pub fn map(&self) -> &Map {
+        &self.map
+    }
+
+    pub fn lazy(&self) -> bool {
+        self.lazy
+    }
+
+    /// The cells expanded by the last search.
+    pub fn search(&self) -> &SearchState {
+        &self.search
+    }
+
+    /// Finds a path from `origin` to `target`, as the cells where it turns.
+    /// Theta* paths are usually, but not always, the shortest any-angle paths.
+    pub fn find_path(&mut self, origin: Position, target: Position) -> PathResult {
+        self.search.reset();
+        self.search.relax(origin, origin, 0);
+
+        let mut frontier = BinaryHeap::new();
+        frontier.push(Reverse((self.heuristic(origin, target), 0, origin, origin)));
+        let mut nodes_expanded = 0;
+        let mut frontier_peak = 1;
+
+        while let Some(Reverse((_, cost, position, parent))) = frontier.pop() {
+            if self.search.closed(position) {
+                continue;
+            }
+            let (cost, parent) = if self.lazy && parent != position {
+                self.verify_parent(position, parent, cost)
+            } else {
+                (cost, parent)
+            };
+            self.search.close(position, parent, cost);
+            nodes_expanded += 1;
+
+            if position == target {
+                return PathResult {
+                    outcome: Some(PathOutcome::Found),
+                    path: self.path_to(origin, target),
+                    cost,
+                    nodes_expanded,
+                    frontier_peak,
+                };
+            }
+
+            for neighbour in self.map.neighbours(position) {
+                if self.search.closed(neighbour)
+                    || self.map.step_cost(position, neighbour).is_none()
+                {
+                    continue;
+                }
+                let via_position = segment_cost(&self.map, position, neighbour)
+                    .map(|step_cost| (cost + step_cost, position));
+                let via_parent = if parent == position {
+                    None
+                } else if self.lazy {
+                    Some((
+                        self.search.cost(parent).unwrap() + self.estimated_cost(parent, neighbour),
+                        parent,
+                    ))
+                } else {
+                    segment_cost(&self.map, parent, neighbour)
+                        .map(|step_cost| (self.search.cost(parent).unwrap() + step_cost, parent))
+                };
+
+                if let Some((neighbour_cost, neighbour_parent)) =
+                    via_parent.into_iter().chain(via_position).min()
+                {
+                    if self
+                        .search
+                        .relax(neighbour, neighbour_parent, neighbour_cost)
+                    {
+                        let priority = neighbour_cost + self.heuristic(neighbour, target);
+                        frontier.push(Reverse((
+                            priority,
+                            neighbour_cost,
+                            neighbour,
+                            neighbour_parent,
+                        )));
+                    }
+                }
+            }
+            frontier_peak = frontier_peak.max(frontier.len());
+        }
+
+        PathResult {
+            outcome: Some(PathOutcome::Unreachable),
+            path: Vec::new(),
+            cost: 0,
+            nodes_expanded,
+            frontier_peak,
+        }
+    }
+
+    /// A lower bound on the cost from one cell to another, along a straight
+    /// line over the cheapest terrain.
+    fn heuristic(&self, a: Position, b: Position) -> Distance {
+        (euclidean_distance(&self.map, a, b) * STEP_COST as f64).floor() as Distance
+            * self.map.costs().min_cost()
+    }
+
+    /// What Lazy Theta* assumes a straight line costs before checking it: its
+    /// length times the more expensive of the cells at its ends.
+    fn estimated_cost(&self, a: Position, b: Position) -> Distance {
+        let cost = |position: Position| {
+            self.map
+                .costs()
+                .get(self.map.terrain()[position])
+                .unwrap_or(1)
+        };
+        (euclidean_distance(&self.map, a, b) * STEP_COST as f64).round() as Distance
+            * cost(a).max(cost(b))
+    }
+
+    /// Checks the parent that Lazy Theta* assumed a cell could see, and if it
+    /// can't, or the line costs more than assumed, falls back to the best
+    /// expanded neighbour.
+    fn verify_parent(
+        &self,
+        position: Position,
+        parent: Position,
+        cost: Distance,
+    ) -> (Distance, Position) {
+        let via_parent = segment_cost(&self.map, parent, position)
+            .map(|step_cost| (self.search.cost(parent).unwrap() + step_cost, parent));
+        if via_parent == Some((cost, parent)) {
+            return (cost, parent);
+        }
+        self.map
+            .neighbours(position)
+            .into_iter()
+            .filter_map(|neighbour| {
+                let neighbour_cost = self.search.cost(neighbour)?;
+                segment_cost(&self.map, neighbour, position)
+                    .map(|step_cost| (neighbour_cost + step_cost, neighbour))
+            }).chain(via_parent)
+            .min()
+            .unwrap()
+    }
+
+    /// Follows the parents back from the target to list the waypoints in order.
+    fn path_to(&self, origin: Position, target: Position) -> Vec<Position> {
+        let mut path = vec![target];
+        let mut current = target;
+        while current != origin {
+            current = self.search.parent(current).unwrap();
+            path.push(current);
+        }
+        path.reverse();
+        path
+    }
+}
+
+/// Builds a random map of walls, with every other cell costing the same.
+#[cfg(test)]
+fn random_walled_map(seed: u64, geometry: Geometry) -> Map {
+    let mut rng = generators::rng_from_seed(seed);
+    generators::random_map(24, geometry, 0.2, &[Terrain::Grass], &mut rng)
+}
+
+#[test]
+fn test_line_of_sight() {
+    for geometry in Geometry::ALL.iter() {
+        let mut map = Map::new(8, 8, *geometry);
+        assert!(line_of_sight(&map, (0, 0), (7, 3)));
+        assert!(line_of_sight(&map, (7, 3), (0, 0)));
+        assert!(line_of_sight(&map, (2, 2), (2, 2)));
+        for y in 0..8 {
+            map.set_terrain((4, y), Terrain::Wall);
+        }
+        assert!(!line_of_sight(&map, (0, 0), (7, 3)));
+        assert!(line_of_sight(&map, (0, 0), (3, 7)));
+    }
+
+    // Lines through a corner can squeeze past walls just like diagonal steps,
+    // and need both cells beside it to be open where diagonal steps do.
+    let mut map = Map::new(3, 3, Geometry::Square8);
+    map.set_terrain((1, 0), Terrain::Wall);
+    assert!(line_of_sight(&map, (0, 0), (2, 2)));
+    assert!(line_of_sight(&map, (0, 1), (2, 2)));
+    assert_eq!(segment_cost(&map, (0, 1), (2, 2)), Some(22 * 2));
+    map.set_costs(TerrainCosts::moving_ai());
+    assert!(!line_of_sight(&map, (0, 0), (1, 1)));
+    assert!(!line_of_sight(&map, (0, 0), (2, 2)));
+    assert!(line_of_sight(&map, (0, 1), (2, 2)));
+}
+
+#[test]
+fn test_theta_star_paths_are_no_longer_than_grid_paths() {
+    for seed in 0..20 {
+        for geometry in Geometry::ALL.iter() {
+            let mut map = random_walled_map(seed, *geometry);
+            let mut costs = *map.costs();
+            costs.set_cut_corners(seed % 2 == 0);
+            map.set_costs(costs);
+            let mut astar =
+                AStarPathfinder::new(map.clone(), (0, 0), (23, 23), SearchStrategy::AStar);
+            let grid_path = astar.get_path();
+
+            for lazy in [false, true].iter() {
+                let mut theta = AnyAngleSearch::new(map.clone(), *lazy);
+                let result = theta.find_path((0, 0), (23, 23));
+                assert_eq!(result.outcome, grid_path.outcome, "seed {}", seed);
+                if !result.found() {
+                    continue;
+                }
+                assert_eq!(result.path.first(), Some(&(0, 0)));
+                assert_eq!(result.path.last(), Some(&(23, 23)));
+                let cost: Distance = result
+                    .path
+                    .windows(2)
+                    .map(|pair| segment_cost(&map, pair[0], pair[1]).unwrap())
+                    .sum();
+                assert_eq!(cost, result.cost);
+                assert!(
+                    result.cost <= grid_path.cost,
+                    "seed {} on {}: {} > {}",
+                    seed,
+                    geometry,
+                    result.cost,
+                    grid_path.cost
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn test_smoothing_pulls_paths_taut() {
+    for seed in 0..20 {
+        for geometry in Geometry::ALL.iter() {
+            let map = random_walled_map(seed, *geometry);
+            let mut astar =
+                AStarPathfinder::new(map.clone(), (0, 0), (23, 23), SearchStrategy::AStar);
+            let path = match astar.get_path().into_path() {
+                Some(path) => path,
+                None => continue,
+            };
+
+            let smoothed = smooth_path(&map, &path);
+            assert_eq!(smoothed.first(), path.first());
+            assert_eq!(smoothed.last(), path.last());
+            assert!(smoothed.len() <= path.len());
+            let mut remaining = path.iter();
+            for waypoint in &smoothed {
+                assert!(remaining.any(|position| position == waypoint));
+            }
+            for pair in smoothed.windows(2) {
+                assert!(line_of_sight(&map, pair[0], pair[1]));
+            }
+        }
+    }
+
+    let map = Map::new(16, 16, Geometry::Square8);
+    let mut astar = AStarPathfinder::new(map.clone(), (0, 0), (15, 6), SearchStrategy::AStar);
+    let path = astar.get_path().into_path().unwrap();
+    assert_eq!(smooth_path(&map, &path), vec![(0, 0), (15, 6)]);
+}
@@ -0,0 +1,124 @@
+use serde_derive::{Deserialize, Serialize};
+
+use super::map::Map;
+use super::pathfinding::{Array2D, Position};
+#[cfg(test)]
+use super::{
+    terrain::{Terrain, TerrainCosts},
+    topology::Geometry,
+};
+
+/// The connected components of a map: groups of passable cells that can all
+/// reach each other, and none of the rest. Once they're labelled, whether
+/// there's any path between two cells can be answered without searching.
+///
+/// Steps between passable cells are allowed in either direction or neither,
+/// so reaching one cell from another means they can reach each other too.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Components {
+    /// The component that each cell belongs to, or `None` if it's impassable.
+    labels: Array2D<Option<usize>>,
+    /// The number of cells in each component.
+    sizes: Vec<usize>,
+}
+
+impl Components {
+    /// Labels the components of a map with a flood fill from each passable
+    /// cell that hasn't been labelled yet.
+    pub fn new(map: &Map) -> Self {
+        let (width, height) = map.len();
+        let mut labels = Array2D::<Option<usize>>::new(width, height);
+        let mut sizes = Vec::new();
+        let mut stack = Vec::new();
+
+        for y in 0..height {
+            for x in 0..width {
+                if labels[(x, y)].is_some() || !map.passable((x, y)) {
+                    continue;
+                }
+
+                let label = sizes.len();
+                let mut size = 0;
+                labels[(x, y)] = Some(label);
+                stack.push((x, y));
+                while let Some(position) = stack.pop() {
+                    size += 1;
+                    for neighbour in map.neighbours(position) {
+                        if labels[neighbour].is_none()
+                            && map.step_cost(position, neighbour).is_some()
+                        {
+                            labels[neighbour] = Some(label);
+                            stack.push(neighbour);
+                        }
+                    }
+                }
+                sizes.push(size);
+            }
+        }
+
+        Self { labels, sizes }
+    }
+
+    /// The component a cell belongs to, or `None` if it's impassable.
+    pub fn label(&self, position: Position) -> Option<usize> {
+        self.labels[position]
+    }
+
+    /// The component each cell belongs to.
+    pub fn labels(&self) -> &Array2D<Option<usize>> {
+        &self.labels
+    }
+
+    /// Whether there's a path between two cells.
+    pub fn connected(&self, a: Position, b: Position) -> bool {
+        match (self.label(a), self.label(b)) {
+            (Some(a), Some(b)) => a == b,
+            _ => false,
+        }
+    }
+
+    /// The number of components.
+    pub fn count(&self) -> usize {
+        self.sizes.len()
+    }
+
+    /// The number of cells in a component.
+    pub fn size(&self, label: usize) -> usize {
+        self.sizes[label]
+    }
+
+    /// The component with the most cells, if there are any passable cells.
+    pub fn largest(&self) -> Option<usize> {
+        (0..self.sizes.len()).max_by_key(|label| self.sizes[*label])
+    }
+}
+
+#[test]
+fn test_components() {
+    // A wall down the middle, and a pocket walled off in the corner.
+    let mut map = Map::new(9, 6, Geometry::Square4);
+    for y in 0..6 {
+        map.set_terrain((4, y), Terrain::Wall);
+    }
+    map.set_terrain((7, 0), Terrain::Wall);
+    map.set_terrain((8, 1), Terrain::Wall);
+
+    let components = Components::new(&map);
+    assert_eq!(components.count(), 3);
+    assert!(components.connected((0, 0), (3, 5)));
+    assert!(!components.connected((0, 0), (5, 0)));
+    assert!(!components.connected((8, 0), (5, 0)));
+    assert!(!components.connected((4, 0), (4, 0)));
+    assert_eq!(components.label((4, 3)), None);
+    assert_eq!(components.size(components.label((8, 0)).unwrap()), 1);
+    assert_eq!(components.largest(), components.label((0, 0)));
+
+    // Moving diagonally can squeeze between the corners of the pocket, unless
+    // the map doesn't let diagonal steps cut corners.
+    map.set_geometry(Geometry::Square8);
+    assert!(Components::new(&map).connected((8, 0), (7, 1)));
+    map.set_costs(TerrainCosts::moving_ai());
+    assert!(!Components::new(&map).connected((8, 0), (7, 1)));
+    map.set_terrain((8, 1), Terrain::Grass);
+    assert!(Components::new(&map).connected((8, 0), (7, 1)));
+}
@@ -0,0 +1,316 @@
+//! Cooperative A*, which plans paths for several agents one at a time through
+//! space and time, each avoiding the cells and moves reserved by the agents
+//! planned before it.
+
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+};
+
+use super::map::Map;
+use super::pathfinding::{Distance, Position};
+use super::topology::STEP_COST;
+#[cfg(test)]
+use super::{generators, terrain::Terrain, topology::Geometry};
+#[cfg(test)]
+use rand::Rng;
+
+/// A moment in a plan, counted in steps from the start. Every move and every
+/// wait takes one step.
+pub type Time = usize;
+
+/// The cells and moves that agents have claimed at each moment.
+#[derive(Clone, Debug, Default)]
+pub struct ReservationTable {
+    /// Cells that are occupied at a given time.
+    cells: HashSet<(Position, Time)>,
+    /// Moves from one cell to another, starting at a given time.
+    moves: HashSet<(Position, Position, Time)>,
+    /// Cells where an agent has stopped for good, and when it arrived.
+    parked: HashMap<Position, Time>,
+    /// The time after which nothing changes any more.
+    horizon: Time,
+}
+
+impl ReservationTable {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether a cell is unoccupied at a given time.
+    pub fn is_free(&self, position: Position, time: Time) -> bool {
+        !self.cells.contains(&(position, time))
+            && self.parked.get(&position).map_or(true, |from| time < *from)
+    }
+
+    /// Whether a cell is unoccupied from a given time onwards, so that an agent
+    /// could stop there.
+    pub fn is_free_from(&self, position: Position, time: Time) -> bool {
+        !self.parked.contains_key(&position)
+            && (time..self.horizon).all(|time| self.is_free(position, time))
+    }
+
+    /// Whether an agent can move from one cell to another, starting at a given
+    /// time, without running into anyone or swapping places with them.
+    pub fn can_move(&self, from: Position, to: Position, time: Time) -> bool {
+        self.is_free(to, time + 1) && !self.moves.contains(&(to, from, time))
+    }
+
+    /// Claims every cell and move along a timed path, and its last cell forever after.
+    pub fn reserve(&mut self, path: &[Position]) {
+        for (time, position) in path.iter().enumerate() {
+            self.cells.insert((*position, time));
+        }
+        for (time, pair) in path.windows(2).enumerate() {
+            self.moves.insert((pair[0], pair[1], time));
+        }
+        if let Some(last) = path.last() {
+            self.parked.insert(*last, path.len() - 1);
+        }
+        self.horizon = self.horizon.max(path.len());
+    }
+
+    /// The time after which nothing changes any more, since every agent has
+    /// stopped for good.
+    pub fn horizon(&self) -> Time {
+        self.horizon
+    }
+}
+
+/// Plans collision-free timed paths for several agents on the same map.
+///
+/// Agents are planned in the order they're added, and earlier agents take
+/// priority: each one keeps to its plan and later agents work around it. An
+/// agent that can't find a path stays where it is, which later agents avoid,
+/// although earlier ones may already have planned to pass through it.
+#[derive(Clone)]
+pub struct CooperativePlanner {
+    /// The grid we're planning over.
+    map: Map,
+    /// The cells and moves claimed by the agents planned so far.
+    reservations: ReservationTable,
+    /// The timed path of each agent, if one was found.
+    paths: Vec<Option<Vec<Position>>>,
+    /// The number of space-time states expanded by the last agent's search.
+    nodes_expanded: usize,
+}
+
+impl CooperativePlanner {
+    pub fn new(map: Map) -> Self {
+        Self {
+            map,
+            reservations: ReservationTable::new(),
+            paths: Vec::new(),
+            nodes_expanded: 0,
+        }
+    }
+
+    /// Plans paths for each of a list of agents, given as (origin, target) pairs.
+    pub fn plan(map: Map, agents: &[(Position, Position)]) -> Vec<Option<Vec<Position>>> {
+        let mut planner = Self::new(map);
+        for (origin, target) in agents {
+            planner.add_agent(*origin, *target);
+        }
+        planner.paths
+    }
+
+    pub fn map(&self) -> &Map {
+        &self.map
+    }
+
+    pub fn reservations(&self) -> &ReservationTable {
+        &self.reservations
+    }
+
+    /// The timed path of each agent, giving its position at each step, if one was found.
+    pub fn paths(&self) -> &[Option<Vec<Position>>] {
+        &self.paths
+    }
+
+    pub fn nodes_expanded(&self) -> usize {
+        self.nodes_expanded
+    }
+
+    /// Plans a path for another agent around those already planned, and
+    /// reserves it for that agent.
+    pub fn add_agent(&mut self, origin: Position, target: Position) -> Option<&[Position]> {
+        let path = self.search(origin, target);
+        self.reservations
+            .reserve(path.as_ref().map_or(&[origin], |path| &path[..]));
+        self.paths.push(path);
+        self.paths.last().unwrap().as_ref().map(|path| &path[..])
+    }
+
+    /// Searches through space and time for the cheapest path to a cell where
+    /// the agent can stop for good. Waiting in place costs as much as the
+    /// cheapest step.
+    ///
+    /// Past the reservations' horizon nothing changes, so all later moments
+    /// are treated as one, which keeps the search finite even when the target
+    /// can never be reached.
+    fn search(&mut self, origin: Position, target: Position) -> Option<Vec<Position>> {
+        let horizon = self.reservations.horizon();
+        let wait_cost = STEP_COST * self.map.costs().min_cost();
+        let state = |position: Position, time: Time| (position, time.min(horizon));
+
+        let mut costs: HashMap<(Position, Time), Distance> = HashMap::new();
+        let mut parents: HashMap<(Position, Time), (Position, Time)> = HashMap::new();
+        let mut closed: HashSet<(Position, Time)> = HashSet::new();
+        let mut frontier = BinaryHeap::new();
+        costs.insert(state(origin, 0), 0);
+        frontier.push(Reverse((self.map.heuristic(origin, target), 0, origin, 0)));
+        self.nodes_expanded = 0;
+
+        while let Some(Reverse((_, cost, position, time))) = frontier.pop() {
+            let current = state(position, time);
+            if !closed.insert(current) {
+                continue;
+            }
+            self.nodes_expanded += 1;
+
+            if position == target && self.reservations.is_free_from(target, time) {
+                let mut path = vec![position];
+                let mut current = current;
+                while let Some(parent) = parents.get(&current) {
+                    path.push(parent.0);
+                    current = *parent;
+                }
+                path.reverse();
+                return Some(path);
+            }
+
+            let moves = self
+                .map
+                .neighbours(position)
+                .into_iter()
+                .filter(|next| self.reservations.can_move(position, *next, time))
+                .filter_map(|next| {
+                    self.map
+                        .step_cost(position, next)
+                        .map(|step_cost| (next, step_cost))
+                });
+            let wait = if self.reservations.is_free(position, time + 1) {
+                Some((position, wait_cost))
+            } else {
+                None
+            };
+
+            for (next, step_cost) in moves.chain(wait) {
+                let next_state = state(next, time + 1);
+                let next_cost = cost + step_cost;
+                if closed.contains(&next_state)
+                    || costs
+                        .get(&next_state)
+                        .map_or(false, |known| *known <= next_cost)
+                {
+                    continue;
+                }
+                costs.insert(next_state, next_cost);
+                parents.insert(next_state, current);
+                frontier.push(Reverse((
+                    next_cost + self.map.heuristic(next, target),
+                    next_cost,
+                    next,
+                    time + 1,
+                )));
+            }
+        }
+
+        None
+    }
+}
+
+/// Asserts that no two agents are ever in the same cell or swap places, and
+/// that each moves only between neighbouring cells.
+#[cfg(test)]
+fn assert_collision_free(map: &Map, paths: &[Vec<Position>]) {
+    let end = paths.iter().map(|path| path.len()).max().unwrap_or(0);
+    let at = |path: &Vec<Position>, time: Time| path[time.min(path.len() - 1)];
+    for time in 0..end {
+        for (i, a) in paths.iter().enumerate() {
+            let (here, next) = (at(a, time), at(a, time + 1));
+            assert!(here == next || map.step_cost(here, next).is_some());
+            for b in &paths[i + 1..] {
+                assert_ne!(here, at(b, time), "collision at time {}", time);
+                assert!(
+                    !(here == at(b, time + 1) && next == at(b, time)),
+                    "swap at time {}",
+                    time
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn test_agents_pass_each_other_in_a_corridor() {
+    // A corridor one cell wide, with a single alcove near the second agent's
+    // end for it to step aside into while the first agent goes past.
+    let mut map = Map::new(9, 3, Geometry::Square4);
+    for x in 0..9 {
+        map.set_terrain((x, 0), Terrain::Wall);
+        if x != 6 {
+            map.set_terrain((x, 2), Terrain::Wall);
+        }
+    }
+    let paths = CooperativePlanner::plan(map.clone(), &[((0, 1), (8, 1)), ((8, 1), (0, 1))]);
+    let paths: Vec<Vec<Position>> = paths.into_iter().map(Option::unwrap).collect();
+    assert_collision_free(&map, &paths);
+    assert_eq!(paths[0].len(), 9);
+    assert!(paths[1].contains(&(6, 2)));
+
+    // Without the alcove, the second agent has no way past.
+    map.set_terrain((6, 2), Terrain::Wall);
+    let paths = CooperativePlanner::plan(map, &[((0, 1), (8, 1)), ((8, 1), (0, 1))]);
+    assert!(paths[0].is_some());
+    assert_eq!(paths[1], None);
+}
+
+#[test]
+fn test_random_crowds_never_collide() {
+    for seed in 0..20 {
+        for geometry in Geometry::ALL.iter() {
+            let mut rng = generators::rng_from_seed(seed);
+            let map = generators::random_map(12, *geometry, 0.2, &[Terrain::Grass], &mut rng);
+
+            // Distinct passable origins and targets for each agent.
+            let mut cells: Vec<Position> = map
+                .terrain()
+                .iter()
+                .filter(|(position, _)| map.passable(*position))
+                .map(|(position, _)| position)
+                .collect();
+            rng.shuffle(&mut cells);
+            let origins = &cells[..8];
+            let mut targets = origins.to_vec();
+            rng.shuffle(&mut targets);
+            let agents: Vec<(Position, Position)> = origins.iter().cloned().zip(targets).collect();
+
+            let mut planner = CooperativePlanner::new(map.clone());
+            for (origin, target) in &agents {
+                planner.add_agent(*origin, *target);
+            }
+            let paths: Vec<Vec<Position>> = planner
+                .paths()
+                .iter()
+                .zip(&agents)
+                .map(|(path, (origin, _))| path.clone().unwrap_or_else(|| vec![*origin]))
+                .collect();
+            for (path, (origin, target)) in planner.paths().iter().zip(&agents) {
+                if let Some(path) = path {
+                    assert_eq!(path.first(), Some(origin));
+                    assert_eq!(path.last(), Some(target));
+                }
+            }
+
+            // Agents that couldn't move may be run into by earlier ones, so
+            // only check the agents planned before the first that failed.
+            let planned = planner
+                .paths()
+                .iter()
+                .position(Option::is_none)
+                .unwrap_or(agents.len());
+            assert_collision_free(&map, &paths[..planned]);
+        }
+    }
+}
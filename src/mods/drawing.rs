@@ -0,0 +1,175 @@
+//! Drawing maps and searches into [Output] frames, shared by the canvas and
+//! the image renderers.
+
+use super::map::Map;
+use super::output::{Output, OutputCircle, OutputLine, OutputPolygon, OutputRect, OutputText};
+use super::pathfinding::{Position, SearchState};
+use super::terrain::Terrain;
+use super::topology::Geometry;
+
+/// The layers that frames are drawn in, from the bottom up.
+pub const LAYERS: [&str; 4] = ["terrain", "search", "paths", "labels"];
+
+/// The colours of the lines and frontier of a search from the origin.
+pub const FORWARD_SEARCH_COLORS: (&str, &str) =
+    ("rgba(192, 192, 64, 1.0)", "rgba(192, 192, 64, 0.5)");
+/// The colours of the lines and frontier of a search back from the target.
+pub const BACKWARD_SEARCH_COLORS: (&str, &str) =
+    ("rgba(64, 160, 192, 1.0)", "rgba(64, 160, 192, 0.5)");
+
+/// An empty frame with room for a map, and its terrain drawn.
+pub fn frame(map: &Map, scale: f64) -> Output {
+    let mut output = Output::new(&LAYERS);
+    let (width, height) = map.topology().screen_size(map.len(), scale);
+    output.width = width.ceil() as u32;
+    output.height = height.ceil() as u32;
+    draw_terrain(map, scale, &mut output);
+    output
+}
+
+/// The colour each type of terrain is drawn in.
+pub fn terrain_color(terrain: Terrain) -> &'static str {
+    use self::Terrain::*;
+    match terrain {
+        Road => "rgba(176, 144, 96, 1.0)",
+        Grass => "rgba(40, 72, 40, 1.0)",
+        Swamp => "rgba(72, 88, 56, 1.0)",
+        Forest => "rgba(16, 96, 48, 1.0)",
+        Mountain => "rgba(128, 112, 104, 1.0)",
+        Water => "rgba(32, 96, 192, 1.0)",
+        Wall => "rgba(192, 0, 64, 1.0)",
+    }
+}
+
+/// Fills each cell with the colour of its terrain, leaving a narrow gap
+/// between cells so the grid can still be made out.
+pub fn draw_terrain(map: &Map, scale: f64, output: &mut Output) {
+    let topology = map.topology();
+    let layer = output.layer("terrain");
+    layer.retained = true;
+    let size = 0.95;
+    for (position, terrain) in map.terrain().iter() {
+        let color = terrain_color(*terrain);
+        let (xp, yp) = topology.cell_center(position, scale);
+        if map.geometry() == Geometry::Hex {
+            layer.polygons.push(OutputPolygon {
+                color,
+                points: topology
+                    .cell_outline(position, scale)
+                    .into_iter()
+                    .map(|(x, y)| (xp + (x - xp) * size, yp + (y - yp) * size))
+                    .collect(),
+            });
+        } else {
+            layer.rects.push(OutputRect {
+                color,
+                x: xp - size * scale / 2.,
+                y: yp - size * scale / 2.,
+                width: size * scale,
+                height: size * scale,
+            });
+        }
+    }
+}
+
+/// Draws the tree of a search, with a line from each cell it's reached back
+/// to its parent and a dot on each cell in its frontier.
+pub fn draw_search(
+    map: &Map,
+    search: &SearchState,
+    (color, frontier_color): (&'static str, &'static str),
+    scale: f64,
+    output: &mut Output,
+) {
+    let topology = map.topology();
+    let layer = output.layer("search");
+    for (position, _) in map.terrain().iter() {
+        if let Some(parent) = search.parent(position) {
+            layer.lines.push(OutputLine {
+                color,
+                width: 0.125 * scale,
+                points: vec![
+                    topology.cell_center(parent, scale),
+                    topology.cell_center(position, scale),
+                ],
+            });
+        }
+    }
+
+    for position in search.frontier() {
+        let (x, y) = topology.cell_center(position, scale);
+        layer.circles.push(OutputCircle {
+            color: frontier_color,
+            x,
+            y,
+            radius: 0.25 * scale,
+        });
+    }
+}
+
+/// Draws a path through the centres of its cells.
+pub fn draw_path(map: &Map, path: &[Position], scale: f64, output: &mut Output) {
+    let topology = map.topology();
+    output.layer("paths").lines.push(OutputLine {
+        color: "rgba(64, 192, 64, 1.0)",
+        width: 0.5 * scale,
+        points: path
+            .iter()
+            .map(|position| topology.cell_center(*position, scale))
+            .collect(),
+    });
+}
+
+/// Labels a cell, such as either end of a path.
+pub fn draw_label(map: &Map, position: Position, text: &str, scale: f64, output: &mut Output) {
+    let (x, y) = map.topology().cell_center(position, scale);
+    output.layer("labels").texts.push(OutputText {
+        color: "rgba(248, 248, 248, 1.0)",
+        x,
+        y,
+        size: 0.75 * scale,
+        text: text.to_string(),
+    });
+}
+
+/// Draws a search that has gone around a wall, for checking the renderers
+/// against the golden images in `src/mods/golden`.
+#[cfg(test)]
+pub fn example_frame(geometry: Geometry) -> Output {
+    use super::pathfinding::{AStarPathfinder, SearchStrategy};
+
+    let mut map = Map::new(8, 5, geometry);
+    for y in 0..4 {
+        map.set_terrain((4, y), Terrain::Wall);
+    }
+    map.set_terrain((2, 4), Terrain::Water);
+    map.set_terrain((6, 3), Terrain::Forest);
+    let mut pathfinder = AStarPathfinder::new(map, (1, 1), (6, 1), SearchStrategy::AStar);
+    let result = pathfinder.get_path();
+    assert!(result.found());
+
+    let scale = 16.;
+    let map = pathfinder.map();
+    let mut output = frame(map, scale);
+    draw_search(
+        map,
+        pathfinder.search(),
+        FORWARD_SEARCH_COLORS,
+        scale,
+        &mut output,
+    );
+    draw_path(map, &result.path, scale, &mut output);
+    draw_label(map, (1, 1), "S", scale, &mut output);
+    draw_label(map, (6, 1), "G", scale, &mut output);
+    output
+}
+
+/// The path of a golden image. Running the tests with `TPRIME_BLESS=1` set
+/// writes new golden images instead of checking against the old ones.
+#[cfg(test)]
+pub fn golden_path(name: &str) -> (std::path::PathBuf, bool) {
+    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("src/mods/golden")
+        .join(name);
+    (path, std::env::var_os("TPRIME_BLESS").is_some())
+}
@@ -0,0 +1,318 @@
+//! D* Lite, which searches backwards from the goal so that when the agent
+//! moves or cells change, only the part of the search that's affected needs to
+//! be repaired instead of starting over.
+
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+use serde_derive::{Deserialize, Serialize};
+
+use super::map::Map;
+use super::pathfinding::{Array2D, Distance, PathOutcome, PathResult, Position};
+use super::terrain::Terrain;
+#[cfg(test)]
+use super::{
+    generators,
+    pathfinding::{AStarPathfinder, SearchStrategy},
+    topology::Geometry,
+};
+#[cfg(test)]
+use rand::Rng;
+
+/// The cost of an unknown or impossible path.
+const INFINITY: Distance = Distance::max_value();
+
+/// The priority of a cell in the queue, compared lexicographically.
+type Key = (Distance, Distance);
+
+/// The search data for a single cell.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+struct DStarCell {
+    /// The cost of the cheapest known path from this cell to the goal.
+    g: Distance,
+    /// A one-step lookahead of `g`, from the costs of this cell's neighbours.
+    rhs: Distance,
+    /// The key this cell is queued with, if it's queued.
+    key: Option<Key>,
+}
+
+impl Default for DStarCell {
+    fn default() -> Self {
+        Self {
+            g: INFINITY,
+            rhs: INFINITY,
+            key: None,
+        }
+    }
+}
+
+/// An incremental search for a path from a moving agent to a fixed goal.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct DStarLite {
+    /// The grid we're searching, as currently known.
+    map: Map,
+    /// Where the agent currently is.
+    start: Position,
+    /// Where the agent is trying to get to.
+    goal: Position,
+    /// How much the heuristic's lower bounds have shrunk by as the agent has
+    /// moved, which is added to new keys instead of updating the old ones.
+    key_modifier: Distance,
+    /// The search data for each cell.
+    cells: Array2D<DStarCell>,
+    /// Cells whose costs are inconsistent, including stale entries that are
+    /// skipped if their key no longer matches the cell's.
+    queue: BinaryHeap<Reverse<(Key, Position)>>,
+    /// The number of cells expanded by the last repair.
+    nodes_expanded: usize,
+    /// The largest the queue has been during the last repair.
+    frontier_peak: usize,
+}
+
+impl DStarLite {
+    /// Prepares a search from `start` to `goal`. Nothing is searched until a path is requested.
+    pub fn new(map: Map, start: Position, goal: Position) -> Self {
+        let cells = Array2D::new(map.width(), map.height());
+        let mut self_ = Self {
+            map,
+            start,
+            goal,
+            key_modifier: 0,
+            cells,
+            queue: BinaryHeap::new(),
+            nodes_expanded: 0,
+            frontier_peak: 0,
+        };
+        self_.cells[goal].rhs = 0;
+        let key = self_.key(goal);
+        self_.enqueue(goal, key);
+        self_
+    }
+
+    pub fn map(&self) -> &Map {
+        &self.map
+    }
+
+    pub fn start(&self) -> Position {
+        self.start
+    }
+
+    pub fn goal(&self) -> Position {
+        self.goal
+    }
+
+    /// The cost of the cheapest path known from a cell to the goal, if it's
+    /// been searched. Cells far from the path may not have been.
+    pub fn cost_to_goal(&self, position: Position) -> Option<Distance> {
+        let cell = self.cells[position];
+        if cell.g == INFINITY || cell.key.is_some() {
+            None
+        } else {
+            Some(cell.g)
+        }
+    }
+
+    /// Records that the agent has moved, usually one step along its path.
+    pub fn move_agent(&mut self, position: Position) {
+        self.key_modifier += self.map.heuristic(self.start, position);
+        self.start = position;
+    }
+
+    /// Marks a cell as impassable.
+    pub fn set_blocked(&mut self, position: Position) {
+        self.set_terrain(position, Terrain::Wall);
+    }
+
+    /// Changes the terrain of a cell and updates the cells whose costs depend on it.
+    pub fn set_terrain(&mut self, position: Position, terrain: Terrain) {
+        if self.map.terrain()[position] == terrain {
+            return;
+        }
+        self.map.set_terrain(position, terrain);
+
+        // The cell's own steps and its neighbours' steps into it have changed,
+        // and so have diagonal steps between neighbours that squeeze past it
+        // if the map doesn't let them cut corners.
+        self.update(position);
+        for neighbour in self.map.neighbours(position) {
+            self.update(neighbour);
+        }
+    }
+
+    /// Repairs the search as needed and returns the cheapest path from the agent to the goal.
+    pub fn path(&mut self) -> PathResult {
+        self.nodes_expanded = 0;
+        self.frontier_peak = self.queue.len();
+        self.compute_shortest_path();
+
+        let cost = self.cells[self.start].g;
+        if cost == INFINITY {
+            return PathResult {
+                outcome: Some(PathOutcome::Unreachable),
+                path: Vec::new(),
+                cost: 0,
+                nodes_expanded: self.nodes_expanded,
+                frontier_peak: self.frontier_peak,
+            };
+        }
+
+        let mut path = vec![self.start];
+        let mut current = self.start;
+        while current != self.goal {
+            current = self
+                .map
+                .neighbours(current)
+                .into_iter()
+                .filter_map(|next| self.cost_through(current, next).map(|cost| (cost, next)))
+                .min()
+                .unwrap()
+                .1;
+            path.push(current);
+        }
+
+        PathResult {
+            outcome: Some(PathOutcome::Found),
+            path,
+            cost,
+            nodes_expanded: self.nodes_expanded,
+            frontier_peak: self.frontier_peak,
+        }
+    }
+
+    fn key(&self, position: Position) -> Key {
+        let cell = self.cells[position];
+        let cost = cell.g.min(cell.rhs);
+        (
+            cost.saturating_add(self.map.heuristic(self.start, position))
+                .saturating_add(self.key_modifier),
+            cost,
+        )
+    }
+
+    fn enqueue(&mut self, position: Position, key: Key) {
+        self.cells[position].key = Some(key);
+        self.queue.push(Reverse((key, position)));
+        self.frontier_peak = self.frontier_peak.max(self.queue.len());
+    }
+
+    /// The cost of a path from `from` to the goal that starts by stepping to `to`.
+    fn cost_through(&self, from: Position, to: Position) -> Option<Distance> {
+        let g = self.cells[to].g;
+        if g == INFINITY {
+            return None;
+        }
+        self.map.step_cost(from, to).map(|cost| cost + g)
+    }
+
+    /// Recomputes a cell's lookahead cost and queues it if it's inconsistent.
+    fn update(&mut self, position: Position) {
+        if position != self.goal {
+            self.cells[position].rhs = self
+                .map
+                .neighbours(position)
+                .into_iter()
+                .filter_map(|next| self.cost_through(position, next))
+                .min()
+                .unwrap_or(INFINITY);
+        }
+        self.cells[position].key = None;
+        let cell = self.cells[position];
+        if cell.g != cell.rhs {
+            let key = self.key(position);
+            self.enqueue(position, key);
+        }
+    }
+
+    /// Expands inconsistent cells until the agent's cost is known to be correct.
+    fn compute_shortest_path(&mut self) {
+        loop {
+            let (key, position) = match self.queue.peek() {
+                Some(Reverse(entry)) => *entry,
+                None => break,
+            };
+            let start = self.cells[self.start];
+            if key >= self.key(self.start) && start.rhs == start.g {
+                break;
+            }
+            self.queue.pop();
+            if self.cells[position].key != Some(key) {
+                continue;
+            }
+
+            let new_key = self.key(position);
+            let cell = self.cells[position];
+            if key < new_key {
+                self.enqueue(position, new_key);
+            } else if cell.g > cell.rhs {
+                self.nodes_expanded += 1;
+                self.cells[position].g = cell.rhs;
+                self.cells[position].key = None;
+                for neighbour in self.map.neighbours(position) {
+                    self.update(neighbour);
+                }
+            } else {
+                self.nodes_expanded += 1;
+                self.cells[position].g = INFINITY;
+                self.update(position);
+                for neighbour in self.map.neighbours(position) {
+                    self.update(neighbour);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_repaired_paths_match_fresh_searches() {
+    for seed in 0..6 {
+        let mut rng = generators::rng_from_seed(seed as u64);
+        let map = Map::demo(seed, Geometry::ALL[seed % 3]);
+        let mut dstar = DStarLite::new(map, (1, 1), (62, 62));
+
+        for _ in 0..20 {
+            let result = dstar.path();
+            let mut astar = AStarPathfinder::new(
+                dstar.map().clone(),
+                dstar.start(),
+                dstar.goal(),
+                SearchStrategy::AStar,
+            );
+            let expected = astar.get_path();
+            assert_eq!(result.outcome, expected.outcome, "seed {}", seed);
+            assert_eq!(result.cost, expected.cost, "seed {}", seed);
+            if !result.found() || result.path.len() < 3 {
+                break;
+            }
+            let cost: Distance = result
+                .path
+                .windows(2)
+                .map(|pair| dstar.map().step_cost(pair[0], pair[1]).unwrap())
+                .sum();
+            assert_eq!(cost, result.cost);
+
+            let ahead = rng.gen_range(2, result.path.len());
+            if result.path[ahead] != dstar.goal() {
+                dstar.set_blocked(result.path[ahead]);
+            }
+            dstar.move_agent(result.path[1]);
+        }
+    }
+}
+
+#[test]
+fn test_repairs_expand_fewer_cells_than_the_first_search() {
+    let mut dstar = DStarLite::new(Map::new(40, 40, Geometry::Square8), (0, 20), (39, 20));
+    let first = dstar.path();
+    assert_eq!(first.outcome, Some(PathOutcome::Found));
+
+    dstar.move_agent(first.path[1]);
+    dstar.set_blocked(first.path[30]);
+    let repaired = dstar.path();
+    assert_eq!(repaired.outcome, Some(PathOutcome::Found));
+    assert!(!repaired.path.contains(&first.path[30]));
+    assert!(repaired.nodes_expanded < first.nodes_expanded / 2);
+
+    for y in 0..40 {
+        dstar.set_blocked((35, y));
+    }
+    assert_eq!(dstar.path().outcome, Some(PathOutcome::Unreachable));
+}
@@ -0,0 +1,244 @@
+//! Distance and flow fields, which record the cheapest way from every cell to
+//! the nearest of a set of sources, so that any number of agents heading for
+//! those sources can share a single search.
+
+use std::{cmp::Reverse, collections::BinaryHeap, fmt};
+
+use serde_derive::{Deserialize, Serialize};
+
+use super::map::Map;
+use super::pathfinding::{Array2D, Distance, Position};
+#[cfg(test)]
+use super::{
+    pathfinding::{AStarPathfinder, SearchStrategy},
+    terrain::{Terrain, TerrainCosts},
+    topology::Geometry,
+};
+
+/// The eight directions between neighbouring cells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Direction {
+    None,
+    Up,
+    UpRight,
+    Right,
+    DownRight,
+    Down,
+    DownLeft,
+    Left,
+    UpLeft,
+}
+
+impl Direction {
+    /// The direction of a step between two neighbouring cells. Every neighbour
+    /// is at most one cell away along each axis, including on hex grids.
+    pub fn between(from: Position, to: Position) -> Self {
+        use self::Direction::*;
+        let dx = to.0 as isize - from.0 as isize;
+        let dy = to.1 as isize - from.1 as isize;
+        match (dx.signum(), dy.signum()) {
+            (0, -1) => Up,
+            (1, -1) => UpRight,
+            (1, 0) => Right,
+            (1, 1) => DownRight,
+            (0, 1) => Down,
+            (-1, 1) => DownLeft,
+            (-1, 0) => Left,
+            (-1, -1) => UpLeft,
+            _ => None,
+        }
+    }
+
+    /// The change in position of a step in this direction.
+    pub fn offset(self) -> (isize, isize) {
+        use self::Direction::*;
+        match self {
+            None => (0, 0),
+            Up => (0, -1),
+            UpRight => (1, -1),
+            Right => (1, 0),
+            DownRight => (1, 1),
+            Down => (0, 1),
+            DownLeft => (-1, 1),
+            Left => (-1, 0),
+            UpLeft => (-1, -1),
+        }
+    }
+
+    /// The cell one step from `position` in this direction.
+    pub fn step(self, position: Position) -> Position {
+        let (dx, dy) = self.offset();
+        (
+            (position.0 as isize + dx) as Distance,
+            (position.1 as isize + dy) as Distance,
+        )
+    }
+}
+
+impl fmt::Display for Direction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use self::Direction::*;
+        write!(
+            f,
+            "{}",
+            match self {
+                None => " ",
+                Up => "⇑",
+                UpRight => "⇗",
+                Right => "⇒",
+                DownRight => "⇘",
+                Down => "⇓",
+                DownLeft => "⇙",
+                Left => "⇐",
+                UpLeft => "⇖",
+            }
+        )
+    }
+}
+
+impl Default for Direction {
+    fn default() -> Self {
+        Direction::None
+    }
+}
+
+/// The cost of the cheapest path from every cell of a map to the nearest of a
+/// set of sources, and the first step along it.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct FlowField {
+    /// The cost from each cell to the nearest source, if any can be reached.
+    distances: Array2D<Option<Distance>>,
+    /// The step each cell takes toward the nearest source, which is
+    /// [Direction::None] for the sources themselves and unreachable cells.
+    directions: Array2D<Direction>,
+}
+
+impl FlowField {
+    /// Runs a full Dijkstra's search outward from `sources`. Impassable
+    /// sources are ignored, since nothing could ever stand on them.
+    pub fn new(map: &Map, sources: &[Position]) -> Self {
+        let mut distances = Array2D::<Option<Distance>>::new(map.width(), map.height());
+        let mut directions = Array2D::<Direction>::new(map.width(), map.height());
+        let mut queue = BinaryHeap::new();
+        for source in sources.iter().filter(|source| map.passable(**source)) {
+            distances[*source] = Some(0);
+            queue.push(Reverse((0, *source)));
+        }
+
+        // The search runs backwards, so each step is taken from a neighbour
+        // into the cell being expanded, and only from neighbours that can be
+        // stood on in the first place.
+        while let Some(Reverse((cost, position))) = queue.pop() {
+            if distances[position] != Some(cost) {
+                continue;
+            }
+            for neighbour in map.neighbours(position) {
+                if !map.passable(neighbour) {
+                    continue;
+                }
+                if let Some(step_cost) = map.step_cost(neighbour, position) {
+                    let next = cost + step_cost;
+                    if distances[neighbour].map_or(true, |known| next < known) {
+                        distances[neighbour] = Some(next);
+                        directions[neighbour] = Direction::between(neighbour, position);
+                        queue.push(Reverse((next, neighbour)));
+                    }
+                }
+            }
+        }
+
+        Self {
+            distances,
+            directions,
+        }
+    }
+
+    pub fn distances(&self) -> &Array2D<Option<Distance>> {
+        &self.distances
+    }
+
+    pub fn directions(&self) -> &Array2D<Direction> {
+        &self.directions
+    }
+
+    /// The cost from a cell to the nearest source, if any can be reached.
+    pub fn distance(&self, position: Position) -> Option<Distance> {
+        self.distances[position]
+    }
+
+    /// The step a cell takes toward the nearest source.
+    pub fn direction(&self, position: Position) -> Direction {
+        self.directions[position]
+    }
+
+    /// The next cell on the way from a cell to the nearest source, or `None`
+    /// for the sources themselves and cells that can't reach any.
+    pub fn next_step(&self, position: Position) -> Option<Position> {
+        match self.directions[position] {
+            Direction::None => None,
+            direction => Some(direction.step(position)),
+        }
+    }
+
+    /// The whole path from a cell to the nearest source, if any can be reached.
+    pub fn path_from(&self, position: Position) -> Option<Vec<Position>> {
+        self.distances[position]?;
+        let mut path = vec![position];
+        let mut current = position;
+        while let Some(next) = self.next_step(current) {
+            path.push(next);
+            current = next;
+        }
+        Some(path)
+    }
+}
+
+#[test]
+fn test_flow_fields_match_searches_to_the_nearest_source() {
+    for geometry in Geometry::ALL.iter() {
+        let map = Map::demo(1, *geometry);
+        let sources = [(1, 1), (62, 62), (8, 40)];
+        let field = FlowField::new(&map, &sources);
+        let mut pathfinder = AStarPathfinder::new(map, (0, 0), (0, 0), SearchStrategy::AStar);
+
+        for x in (0..64).step_by(7) {
+            for y in (0..64).step_by(5) {
+                let position = (x, y);
+                let expected = pathfinder.find_nearest(position, &sources);
+                if !pathfinder.map().passable(position) || !expected.found() {
+                    assert_eq!(field.path_from(position), None, "{:?}", position);
+                    continue;
+                }
+                assert_eq!(field.distance(position), Some(expected.cost));
+
+                let path = field.path_from(position).unwrap();
+                assert!(sources.contains(path.last().unwrap()));
+                let cost: Distance = path
+                    .windows(2)
+                    .map(|pair| {
+                        assert!(pathfinder.map().neighbours(pair[0]).contains(&pair[1]));
+                        pathfinder.map().step_cost(pair[0], pair[1]).unwrap()
+                    }).sum();
+                assert_eq!(cost, expected.cost, "{:?} on {}", position, geometry);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_flow_field_directions() {
+    let mut map = Map::new(3, 3, Geometry::Square8);
+    map.set_terrain((1, 0), Terrain::Wall);
+    let field = FlowField::new(&map, &[(1, 1), (1, 0)]);
+    assert_eq!(field.direction((1, 1)), Direction::None);
+    assert_eq!(field.distance((1, 0)), None);
+    assert_eq!(field.direction((0, 0)), Direction::DownRight);
+    assert_eq!(field.direction((2, 2)), Direction::UpLeft);
+    assert_eq!(field.next_step((2, 1)), Some((1, 1)));
+    assert_eq!(field.direction((2, 2)).to_string(), "⇖");
+
+    // Without cutting the wall's corner, the way round is straight down.
+    map.set_costs(TerrainCosts::moving_ai());
+    let field = FlowField::new(&map, &[(1, 1)]);
+    assert_eq!(field.direction((0, 0)), Direction::Down);
+}
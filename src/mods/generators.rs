@@ -0,0 +1,752 @@
+//! Seeded procedural map generators. Each one draws from the same ChaCha
+//! generator that the web application uses, so a seed always reproduces the
+//! same map on every platform.
+
+use std::{cmp::Reverse, collections::BinaryHeap, fmt, str::FromStr};
+
+use rand::{prng::chacha::ChaChaCore, Rng, SeedableRng};
+use rand_core::block::BlockRng;
+use serde_derive::{Deserialize, Serialize};
+
+use super::components::Components;
+use super::map::Map;
+#[cfg(test)]
+use super::mapfile;
+use super::pathfinding::{Array2D, Distance, Position};
+use super::terrain::Terrain;
+use super::topology::Geometry;
+
+/// The random number generator every generator draws from.
+pub type GeneratorRng = BlockRng<ChaChaCore>;
+
+/// Constructs the generator for a seed.
+pub fn rng_from_seed(seed: u64) -> GeneratorRng {
+    let mut bytes = [0u8; 32];
+    for i in 0..8 {
+        bytes[i] = (seed >> (8 * i)) as u8;
+    }
+    BlockRng::new(ChaChaCore::from_seed(bytes))
+}
+
+/// Selects one of the map generators.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Generator {
+    /// A perfect maze carved by a randomized depth-first search, which makes
+    /// long winding passages with few branches.
+    RecursiveBacktracker,
+    /// A perfect maze grown outward from one cell by randomized Prim's
+    /// algorithm, which makes many short dead ends.
+    Prim,
+    /// A perfect maze joined together from single cells by randomized
+    /// Kruskal's algorithm.
+    Kruskal,
+    /// Open caves smoothed out of random noise by a cellular automaton.
+    Caves,
+    /// Rectangular rooms joined by corridors.
+    Dungeon,
+    /// Walls scattered independently over open ground.
+    Obstacles,
+}
+
+impl Generator {
+    /// Every generator, in the order the demo cycles through them.
+    pub const ALL: [Generator; 6] = [
+        Generator::RecursiveBacktracker,
+        Generator::Prim,
+        Generator::Kruskal,
+        Generator::Caves,
+        Generator::Dungeon,
+        Generator::Obstacles,
+    ];
+
+    /// Generates a map of the given size from a seed.
+    pub fn generate(self, width: Distance, height: Distance, geometry: Geometry, seed: u64) -> Map {
+        let mut rng = rng_from_seed(seed);
+        let rng = &mut rng;
+        use self::Generator::*;
+        match self {
+            RecursiveBacktracker => recursive_backtracker(width, height, geometry, rng),
+            Prim => prim(width, height, geometry, rng),
+            Kruskal => kruskal(width, height, geometry, rng),
+            Caves => caves(width, height, geometry, rng),
+            Dungeon => dungeon(width, height, geometry, rng),
+            Obstacles => obstacles(width, height, geometry, 0.3, rng),
+        }
+    }
+
+    /// Generates a map of the given size from a seed, with a route between
+    /// the two ends of `ends`, which is made by `repair` if the map from that
+    /// seed doesn't already have one.
+    pub fn generate_solvable(
+        self,
+        width: Distance,
+        height: Distance,
+        geometry: Geometry,
+        seed: u64,
+        ends: (Position, Position),
+        repair: Repair,
+    ) -> Map {
+        let (origin, target) = ends;
+        let attempts = match repair {
+            Repair::Carve => 1,
+            Repair::Reroll => MAX_REROLLS,
+        };
+        let mut map = self.generate(width, height, geometry, seed);
+        for attempt in 1..attempts {
+            if Components::new(&map).connected(origin, target) {
+                return map;
+            }
+            map = self.generate(width, height, geometry, seed.wrapping_add(attempt));
+        }
+        if !Components::new(&map).connected(origin, target) {
+            carve_corridor(&mut map, origin, target);
+        }
+        map
+    }
+}
+
+/// How many seeds [Repair::Reroll] tries before it falls back to carving.
+const MAX_REROLLS: u64 = 64;
+
+/// How a generated map is made solvable when there's no route between its ends.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Repair {
+    /// Carves the fewest walls needed to join the ends, keeping the map
+    /// from the original seed.
+    Carve,
+    /// Tries the following seeds in turn until one generates a map with a
+    /// route, and carves one if they all fail.
+    Reroll,
+}
+
+impl FromStr for Generator {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        use self::Generator::*;
+        match s {
+            "backtracker" => Ok(RecursiveBacktracker),
+            "prim" => Ok(Prim),
+            "kruskal" => Ok(Kruskal),
+            "caves" => Ok(Caves),
+            "dungeon" => Ok(Dungeon),
+            "obstacles" => Ok(Obstacles),
+            _ => Err(format!(
+                "unknown generator {:?}, expected backtracker, prim, kruskal, caves, dungeon or obstacles",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Generator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use self::Generator::*;
+        write!(
+            f,
+            "{}",
+            match self {
+                RecursiveBacktracker => "recursive backtracker maze",
+                Prim => "Prim's maze",
+                Kruskal => "Kruskal's maze",
+                Caves => "cellular automaton caves",
+                Dungeon => "rooms and corridors",
+                Obstacles => "scattered obstacles",
+            }
+        )
+    }
+}
+
+/// A map of the given size with every cell set to `terrain`.
+fn filled(width: Distance, height: Distance, geometry: Geometry, terrain: Terrain) -> Map {
+    let mut map = Map::new(width, height, geometry);
+    for x in 0..width {
+        for y in 0..height {
+            map.set_terrain((x, y), terrain);
+        }
+    }
+    map
+}
+
+/// The grid that mazes are carved into. Maze cells are the map cells with
+/// odd coordinates, and the cell between two neighbouring maze cells is the
+/// passage joining them, once it's carved.
+struct MazeGrid {
+    map: Map,
+}
+
+impl MazeGrid {
+    /// A map with nothing carved out of it yet.
+    fn new(width: Distance, height: Distance, geometry: Geometry) -> Self {
+        Self {
+            map: filled(width, height, geometry, Terrain::Wall),
+        }
+    }
+
+    /// Every maze cell.
+    fn cells(&self) -> Vec<Position> {
+        let (width, height) = self.map.len();
+        (0..height / 2)
+            .flat_map(|y| (0..width / 2).map(move |x| (2 * x + 1, 2 * y + 1)))
+            .filter(|position| self.map.contains(*position))
+            .collect()
+    }
+
+    /// The maze cells next to a maze cell, along with the passages to them.
+    /// Mazes on square grids only use straight passages, since a diagonal one
+    /// would have to squeeze between the corners of walls.
+    fn neighbours(&self, (x, y): Position) -> Vec<(Position, Position)> {
+        let offsets: &[(isize, isize)] = if self.map.geometry() == Geometry::Hex {
+            &[(0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1)]
+        } else {
+            &[(0, -1), (-1, 0), (1, 0), (0, 1)]
+        };
+        let (x, y) = (x as isize, y as isize);
+        offsets
+            .iter()
+            .map(|(dx, dy)| ((x + 2 * dx, y + 2 * dy), (x + dx, y + dy)))
+            .filter(|((x, y), _)| *x >= 0 && *y >= 0)
+            .map(|((x, y), (px, py))| {
+                (
+                    (x as Distance, y as Distance),
+                    (px as Distance, py as Distance),
+                )
+            }).filter(|(position, _)| self.map.contains(*position))
+            .collect()
+    }
+
+    fn carve(&mut self, position: Position) {
+        self.map.set_terrain(position, Terrain::Grass);
+    }
+
+    fn carved(&self, position: Position) -> bool {
+        self.map.terrain()[position] != Terrain::Wall
+    }
+}
+
+/// Carves a perfect maze with a randomized depth-first search: it keeps
+/// tunnelling into unvisited cells, backtracking when it reaches a dead end.
+pub fn recursive_backtracker(
+    width: Distance,
+    height: Distance,
+    geometry: Geometry,
+    rng: &mut GeneratorRng,
+) -> Map {
+    let mut maze = MazeGrid::new(width, height, geometry);
+    let cells = maze.cells();
+    if cells.is_empty() {
+        return maze.map;
+    }
+    let start = cells[rng.gen_range(0, cells.len())];
+    maze.carve(start);
+    let mut stack = vec![start];
+
+    while let Some(&current) = stack.last() {
+        let unvisited: Vec<(Position, Position)> = maze
+            .neighbours(current)
+            .into_iter()
+            .filter(|(next, _)| !maze.carved(*next))
+            .collect();
+        if unvisited.is_empty() {
+            stack.pop();
+            continue;
+        }
+        let (next, passage) = unvisited[rng.gen_range(0, unvisited.len())];
+        maze.carve(passage);
+        maze.carve(next);
+        stack.push(next);
+    }
+
+    maze.map
+}
+
+/// Grows a perfect maze outward from a single cell with randomized Prim's
+/// algorithm: it repeatedly carves a random passage from the maze so far to a
+/// cell that isn't part of it yet.
+pub fn prim(width: Distance, height: Distance, geometry: Geometry, rng: &mut GeneratorRng) -> Map {
+    let mut maze = MazeGrid::new(width, height, geometry);
+    let cells = maze.cells();
+    if cells.is_empty() {
+        return maze.map;
+    }
+    let start = cells[rng.gen_range(0, cells.len())];
+    maze.carve(start);
+    let mut edges = maze.neighbours(start);
+
+    while !edges.is_empty() {
+        let (next, passage) = edges.swap_remove(rng.gen_range(0, edges.len()));
+        if maze.carved(next) {
+            continue;
+        }
+        maze.carve(passage);
+        maze.carve(next);
+        edges.extend(
+            maze.neighbours(next)
+                .into_iter()
+                .filter(|(cell, _)| !maze.carved(*cell)),
+        );
+    }
+
+    maze.map
+}
+
+/// Joins single cells into a perfect maze with randomized Kruskal's
+/// algorithm: it considers every passage in a random order, carving the ones
+/// between cells that aren't connected yet.
+pub fn kruskal(
+    width: Distance,
+    height: Distance,
+    geometry: Geometry,
+    rng: &mut GeneratorRng,
+) -> Map {
+    let mut maze = MazeGrid::new(width, height, geometry);
+    let cells = maze.cells();
+    let mut passages: Vec<(Position, Position, Position)> = Vec::new();
+    for cell in &cells {
+        maze.carve(*cell);
+        for (next, passage) in maze.neighbours(*cell) {
+            if next > *cell {
+                passages.push((*cell, next, passage));
+            }
+        }
+    }
+    rng.shuffle(&mut passages);
+
+    // Each cell points toward a representative of the set it's been joined to.
+    let mut sets = Array2D::<Option<Position>>::new(width, height);
+    fn find(sets: &mut Array2D<Option<Position>>, position: Position) -> Position {
+        match sets[position] {
+            None => position,
+            Some(parent) => {
+                let root = find(sets, parent);
+                sets[position] = Some(root);
+                root
+            }
+        }
+    }
+
+    for (a, b, passage) in passages {
+        let (root_a, root_b) = (find(&mut sets, a), find(&mut sets, b));
+        if root_a != root_b {
+            sets[root_a] = Some(root_b);
+            maze.carve(passage);
+        }
+    }
+
+    maze.map
+}
+
+/// Smooths random noise into caves with a cellular automaton: each step, a
+/// cell becomes a wall if most of the cells around it, counting itself and
+/// anything beyond the edge of the map as walls, already are.
+pub fn caves(width: Distance, height: Distance, geometry: Geometry, rng: &mut GeneratorRng) -> Map {
+    let mut map = obstacles(width, height, geometry, 0.45, rng);
+    for _ in 0..5 {
+        let previous = map.clone();
+        for x in 0..width {
+            for y in 0..height {
+                let neighbours = previous.neighbours((x, y));
+                let outside =
+                    geometry.topology().neighbours((1, 1), (3, 3)).len() - neighbours.len();
+                let walls = outside
+                    + neighbours
+                        .iter()
+                        .chain(Some(&(x, y)))
+                        .filter(|position| !previous.passable(**position))
+                        .count();
+                let terrain = if 2 * walls > outside + neighbours.len() + 1 {
+                    Terrain::Wall
+                } else {
+                    Terrain::Grass
+                };
+                map.set_terrain((x, y), terrain);
+            }
+        }
+    }
+    map
+}
+
+/// Places non-overlapping rectangular rooms at random, then joins each one to
+/// the one placed before it with a corridor that turns once.
+pub fn dungeon(
+    width: Distance,
+    height: Distance,
+    geometry: Geometry,
+    rng: &mut GeneratorRng,
+) -> Map {
+    let mut map = filled(width, height, geometry, Terrain::Wall);
+    if width < 5 || height < 5 {
+        return map;
+    }
+
+    // Rooms as (left, top, width, height), kept a cell apart from each other
+    // and the edge of the map.
+    let mut rooms: Vec<(Distance, Distance, Distance, Distance)> = Vec::new();
+    for _ in 0..(width * height / 64).max(1) {
+        let room_width = rng.gen_range(3, (width / 4).max(4));
+        let room_height = rng.gen_range(3, (height / 4).max(4));
+        if room_width + 2 > width || room_height + 2 > height {
+            continue;
+        }
+        let left = rng.gen_range(1, width - room_width);
+        let top = rng.gen_range(1, height - room_height);
+        let overlaps = rooms.iter().any(|&(x, y, w, h)| {
+            left <= x + w && x <= left + room_width && top <= y + h && y <= top + room_height
+        });
+        if !overlaps {
+            rooms.push((left, top, room_width, room_height));
+        }
+    }
+
+    for (i, &(left, top, room_width, room_height)) in rooms.iter().enumerate() {
+        for x in left..left + room_width {
+            for y in top..top + room_height {
+                map.set_terrain((x, y), Terrain::Grass);
+            }
+        }
+
+        if i > 0 {
+            let (x0, y0, w0, h0) = rooms[i - 1];
+            let from = (x0 + w0 / 2, y0 + h0 / 2);
+            let to = (left + room_width / 2, top + room_height / 2);
+            let corner = if rng.gen_bool(0.5) {
+                (to.0, from.1)
+            } else {
+                (from.0, to.1)
+            };
+            for position in straight_line(from, corner)
+                .into_iter()
+                .chain(straight_line(corner, to))
+            {
+                if map.terrain()[position] == Terrain::Wall {
+                    map.set_terrain(position, Terrain::Road);
+                }
+            }
+        }
+    }
+
+    map
+}
+
+/// Carves a corridor between two cells, turning as few impassable cells into
+/// grass as it can. Afterward, there's a route between them.
+pub fn carve_corridor(map: &mut Map, from: Position, to: Position) {
+    // Dijkstra's search where impassable cells cost far more to pass through.
+    // Diagonal steps are only taken where they don't cut a corner, so the
+    // corridor is open whether or not the map lets them cut corners.
+    const OPEN_COST: Distance = 1;
+    const CARVE_COST: Distance = 16;
+    let (width, height) = map.len();
+    let mut costs = Array2D::<Option<Distance>>::new(width, height);
+    let mut parents = Array2D::<Option<Position>>::new(width, height);
+    let mut frontier = BinaryHeap::new();
+    costs[from] = Some(0);
+    frontier.push(Reverse((0, from)));
+
+    while let Some(Reverse((cost, position))) = frontier.pop() {
+        if position == to {
+            break;
+        }
+        if costs[position].map_or(false, |best| best < cost) {
+            continue;
+        }
+        for neighbour in map.neighbours(position) {
+            let corners = map.topology().corners(position, neighbour);
+            if !corners.into_iter().all(|corner| map.passable(corner)) {
+                continue;
+            }
+            let cost = cost
+                + if map.passable(neighbour) {
+                    OPEN_COST
+                } else {
+                    CARVE_COST
+                };
+            if costs[neighbour].map_or(true, |best| cost < best) {
+                costs[neighbour] = Some(cost);
+                parents[neighbour] = Some(position);
+                frontier.push(Reverse((cost, neighbour)));
+            }
+        }
+    }
+
+    let mut position = to;
+    loop {
+        if !map.passable(position) {
+            map.set_terrain(position, Terrain::Grass);
+        }
+        match parents[position] {
+            Some(parent) if position != from => position = parent,
+            _ => break,
+        }
+    }
+}
+
+/// The cells along a horizontal or vertical line, including both ends.
+fn straight_line(from: Position, to: Position) -> Vec<Position> {
+    let (x0, x1) = (from.0.min(to.0), from.0.max(to.0));
+    let (y0, y1) = (from.1.min(to.1), from.1.max(to.1));
+    (x0..=x1)
+        .flat_map(|x| (y0..=y1).map(move |y| (x, y)))
+        .collect()
+}
+
+/// Scatters walls over open ground, each cell independently having a
+/// `density` chance of being one.
+pub fn obstacles(
+    width: Distance,
+    height: Distance,
+    geometry: Geometry,
+    density: f64,
+    rng: &mut GeneratorRng,
+) -> Map {
+    let mut map = Map::new(width, height, geometry);
+    for x in 0..width {
+        for y in 0..height {
+            if rng.gen_bool(density) {
+                map.set_terrain((x, y), Terrain::Wall);
+            }
+        }
+    }
+    map
+}
+
+/// A random square map for testing searches on, with each cell a wall with
+/// probability `density` as in [obstacles], and otherwise one of the `open`
+/// kinds of terrain, chosen evenly. The first and last corners are always
+/// open, so they can be searched between.
+#[cfg(test)]
+pub fn random_map(
+    size: Distance,
+    geometry: Geometry,
+    density: f64,
+    open: &[Terrain],
+    rng: &mut GeneratorRng,
+) -> Map {
+    let mut map = obstacles(size, size, geometry, density, rng);
+    for x in 0..size {
+        for y in 0..size {
+            if map.terrain()[(x, y)] != Terrain::Wall {
+                map.set_terrain((x, y), open[rng.gen_range(0, open.len())]);
+            }
+        }
+    }
+    map.set_terrain((0, 0), open[0]);
+    map.set_terrain((size - 1, size - 1), open[0]);
+    map
+}
+
+/// Counts the passable cells that can be reached from the first one.
+#[cfg(test)]
+fn reachable_and_passable(map: &Map) -> (usize, usize) {
+    let passable: Vec<Position> = map
+        .terrain()
+        .iter()
+        .map(|(position, _)| position)
+        .filter(|position| map.passable(*position))
+        .collect();
+    let mut seen = Array2D::<bool>::new(map.width(), map.height());
+    let mut stack: Vec<Position> = passable.iter().take(1).cloned().collect();
+    let mut reachable = 0;
+    while let Some(position) = stack.pop() {
+        if seen[position] {
+            continue;
+        }
+        seen[position] = true;
+        reachable += 1;
+        for neighbour in map.neighbours(position) {
+            if !seen[neighbour] && map.step_cost(position, neighbour).is_some() {
+                stack.push(neighbour);
+            }
+        }
+    }
+    (reachable, passable.len())
+}
+
+#[test]
+fn test_seeds_reproduce_maps() {
+    // Each generator's map for seed 1, as it was when the generators were
+    // written. If one of these changes, so has every map shared by its seed.
+    let golden = [
+        (
+            Generator::RecursiveBacktracker,
+            "\
+###############
+#...#.....#...#
+#.#.#.#.###.#.#
+#.#...#.....#.#
+#.###########.#
+#.#.#.....#...#
+#.#.#####.#.#.#
+#.......#...#.#
+###############
+",
+        ),
+        (
+            Generator::Prim,
+            "\
+###############
+#...#.#.#.....#
+###.#.#.#.#####
+#...#...#...#.#
+#.#.#.###.#.#.#
+#.#.#.....#...#
+###.#.#.#####.#
+#.....#...#...#
+###############
+",
+        ),
+        (
+            Generator::Kruskal,
+            "\
+###############
+#.............#
+###.#####.#.###
+#.......#.#.#.#
+#.###.#.#.#.#.#
+#.#...#.#.#.#.#
+#.#.#.#####.#.#
+#.#.#.....#...#
+###############
+",
+        ),
+        (
+            Generator::Caves,
+            "\
+#...###########
+......#########
+.......########
+#........######
+#.........#####
+#.........#####
+##..##....#####
+#######..######
+###############
+",
+        ),
+        (
+            Generator::Dungeon,
+            "\
+###############
+###############
+###############
+###############
+##...##########
+##...===...####
+##...###...####
+########...####
+###############
+",
+        ),
+        (
+            Generator::Obstacles,
+            "\
+..#.#..##.#....
+......##...#..#
+...#.#....#.#.#
+.......#..##...
+......#..#.#..#
+.##.#..#..##...
+.........##.#..
+..#..##...##.##
+#..##........##
+",
+        ),
+    ];
+    for (generator, expected) in golden.iter() {
+        let map = generator.generate(15, 9, Geometry::Square8, 1);
+        assert_eq!(
+            mapfile::write_ascii(&map, None, None),
+            *expected,
+            "{}",
+            generator
+        );
+    }
+
+    for generator in Generator::ALL.iter() {
+        for geometry in Geometry::ALL.iter() {
+            let draw = |seed| {
+                mapfile::write_ascii(&generator.generate(33, 25, *geometry, seed), None, None)
+            };
+            assert_ne!(draw(7), draw(8), "{} on {}", generator, geometry);
+        }
+    }
+}
+
+#[test]
+fn test_solvable_maps_connect_their_ends() {
+    let ends = ((1, 1), (31, 23));
+    for generator in Generator::ALL.iter() {
+        for geometry in Geometry::ALL.iter() {
+            for repair in [Repair::Carve, Repair::Reroll].iter() {
+                for seed in 0..5 {
+                    let map = generator.generate_solvable(33, 25, *geometry, seed, ends, *repair);
+                    assert!(
+                        Components::new(&map).connected(ends.0, ends.1),
+                        "{} on {} repaired by {:?}",
+                        generator,
+                        geometry,
+                        repair
+                    );
+                }
+            }
+        }
+    }
+
+    // Carving only opens up cells, and the map stays the one from the seed.
+    let walled = Generator::Caves.generate(33, 25, Geometry::Square8, 3);
+    let carved =
+        Generator::Caves.generate_solvable(33, 25, Geometry::Square8, 3, ends, Repair::Carve);
+    for (position, terrain) in walled.terrain().iter() {
+        assert!(carved.terrain()[position] == *terrain || !walled.passable(position));
+    }
+}
+
+#[test]
+fn test_mazes_are_perfect() {
+    for generator in [
+        Generator::RecursiveBacktracker,
+        Generator::Prim,
+        Generator::Kruskal,
+    ]
+    .iter()
+    {
+        for geometry in Geometry::ALL.iter() {
+            for seed in 0..5 {
+                let map = generator.generate(33, 25, *geometry, seed);
+                let maze = MazeGrid { map };
+                let cells = maze.cells().len();
+
+                // Every cell is connected, by exactly one fewer passage than
+                // there are cells, so there are no loops.
+                let (reachable, passable) = reachable_and_passable(&maze.map);
+                assert_eq!(reachable, passable, "{} on {}", generator, geometry);
+                assert_eq!(passable - cells, cells - 1, "{} on {}", generator, geometry);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_open_generators_leave_room_to_move() {
+    for geometry in Geometry::ALL.iter() {
+        for seed in 0..5 {
+            let (_, open) =
+                reachable_and_passable(&Generator::Obstacles.generate(40, 40, *geometry, seed));
+            assert!(open > 1600 * 6 / 10 && open < 1600 * 8 / 10);
+
+            let (_, open) =
+                reachable_and_passable(&Generator::Caves.generate(40, 40, *geometry, seed));
+            assert!(open > 1600 / 5, "caves on {} with seed {}", geometry, seed);
+
+            // Corridors join the rooms up into one connected dungeon.
+            let map = Generator::Dungeon.generate(40, 40, *geometry, seed);
+            let (reachable, open) = reachable_and_passable(&map);
+            assert_eq!(
+                reachable, open,
+                "dungeon on {} with seed {}",
+                geometry, seed
+            );
+            assert!(open > 40);
+        }
+    }
+}
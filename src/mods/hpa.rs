@@ -0,0 +1,509 @@
+//! Hierarchical pathfinding (HPA*), which splits a large map into square
+//! clusters and searches a much smaller abstract graph of the entrances
+//! between them, before refining each step of the abstract path with A*.
+//!
+//! Paths are valid but only near-optimal, because they're forced to cross
+//! between clusters at a few chosen entrances.
+
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+};
+
+#[cfg(test)]
+use super::generators;
+use super::map::Map;
+use super::pathfinding::{
+    AStarPathfinder, Array2D, Distance, PathOutcome, PathResult, Position, SearchStrategy,
+};
+use super::terrain::Terrain;
+use super::topology::Geometry;
+#[cfg(test)]
+use rand::Rng;
+
+/// The edges leaving each node of the abstract graph, with their costs.
+type Graph = HashMap<Position, Vec<(Position, Distance)>>;
+
+/// Entrances that are at least this long get a transition at each end instead
+/// of one in the middle.
+const LONG_ENTRANCE: Distance = 6;
+
+/// The precomputed connections between the entrances of a single cluster.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Cluster {
+    /// The cheapest cost from one entrance to another within the cluster, for
+    /// each pair that are connected.
+    edges: Vec<(Position, Position, Distance)>,
+}
+
+/// A map divided into clusters, with an abstract graph of the transitions
+/// between them.
+pub struct Hierarchy {
+    /// The grid we're searching.
+    map: Map,
+    /// The width and height of each cluster, in cells.
+    cluster_size: Distance,
+    /// The intra-cluster edges of each cluster.
+    clusters: Array2D<Cluster>,
+    /// The pairs of adjacent cells that paths may use to cross from each
+    /// cluster to the one on its right, and to the one below it.
+    transitions: Array2D<[Vec<(Position, Position)>; 2]>,
+    /// Every intra- and inter-cluster edge, by the node it leaves.
+    graph: Graph,
+    /// How many times a cluster's edges have been computed.
+    clusters_built: usize,
+}
+
+impl Hierarchy {
+    /// Divides a map into clusters and computes the abstract graph, or returns
+    /// `None` for hex maps, where paths can cross between clusters diagonally
+    /// and entrances made of straight steps would miss some routes.
+    pub fn new(map: Map, cluster_size: Distance) -> Option<Self> {
+        assert!(cluster_size > 0, "clusters can't be empty");
+        if map.geometry() == Geometry::Hex {
+            return None;
+        }
+
+        let (width, height) = map.len();
+        let clusters_size = (
+            (width + cluster_size - 1) / cluster_size,
+            (height + cluster_size - 1) / cluster_size,
+        );
+        let mut self_ = Self {
+            map,
+            cluster_size,
+            clusters: Array2D::new(clusters_size.0, clusters_size.1),
+            transitions: Array2D::new(clusters_size.0, clusters_size.1),
+            graph: Graph::new(),
+            clusters_built: 0,
+        };
+
+        for cx in 0..clusters_size.0 {
+            for cy in 0..clusters_size.1 {
+                for side in 0..2 {
+                    self_.transitions[(cx, cy)][side] = self_.find_transitions((cx, cy), side);
+                }
+            }
+        }
+        for cx in 0..clusters_size.0 {
+            for cy in 0..clusters_size.1 {
+                self_.clusters[(cx, cy)] = self_.build_cluster((cx, cy));
+            }
+        }
+        self_.build_graph();
+
+        Some(self_)
+    }
+
+    pub fn map(&self) -> &Map {
+        &self.map
+    }
+
+    pub fn cluster_size(&self) -> Distance {
+        self.cluster_size
+    }
+
+    /// The number of times any cluster's internal edges have been computed,
+    /// including when the hierarchy was first built.
+    pub fn clusters_built(&self) -> usize {
+        self.clusters_built
+    }
+
+    /// The nodes of the abstract graph, which are the cells on either side of
+    /// each transition between clusters.
+    pub fn entrances(&self) -> impl Iterator<Item = Position> + '_ {
+        self.graph.keys().cloned()
+    }
+
+    /// Changes the terrain of a cell, recomputing the entrances and edges of the
+    /// cluster it's in, and of any neighbouring cluster whose entrances it affects.
+    pub fn set_terrain(&mut self, position: Position, terrain: Terrain) {
+        self.map.set_terrain(position, terrain);
+
+        let cluster = self.cluster_of(position);
+        let (min, max) = self.cluster_bounds(cluster);
+        let mut affected = vec![cluster];
+
+        // The borders this cell is on, as the cluster to the left or above the
+        // border, which side of that cluster it is, and the cluster across it.
+        let mut borders = Vec::new();
+        if position.0 + 1 == max.0 && cluster.0 + 1 < self.clusters.len().0 {
+            borders.push((cluster, 0, (cluster.0 + 1, cluster.1)));
+        }
+        if position.1 + 1 == max.1 && cluster.1 + 1 < self.clusters.len().1 {
+            borders.push((cluster, 1, (cluster.0, cluster.1 + 1)));
+        }
+        if position.0 == min.0 && cluster.0 > 0 {
+            let left = (cluster.0 - 1, cluster.1);
+            borders.push((left, 0, left));
+        }
+        if position.1 == min.1 && cluster.1 > 0 {
+            let above = (cluster.0, cluster.1 - 1);
+            borders.push((above, 1, above));
+        }
+
+        for (owner, side, neighbour) in borders {
+            let transitions = self.find_transitions(owner, side);
+            if transitions != self.transitions[owner][side] {
+                self.transitions[owner][side] = transitions;
+                affected.push(neighbour);
+            }
+        }
+
+        for cluster in affected {
+            self.clusters[cluster] = self.build_cluster(cluster);
+        }
+        self.build_graph();
+    }
+
+    /// Finds a path by searching the abstract graph and then refining each of
+    /// its steps with A* inside a single cluster.
+    pub fn find_path(&mut self, origin: Position, target: Position) -> PathResult {
+        let mut nodes_expanded = 0;
+        let unreachable = |nodes_expanded, frontier_peak| PathResult {
+            outcome: Some(PathOutcome::Unreachable),
+            path: Vec::new(),
+            cost: 0,
+            nodes_expanded,
+            frontier_peak,
+        };
+
+        if !self.map.passable(origin) || !self.map.passable(target) {
+            return unreachable(0, 0);
+        }
+
+        // Temporarily connect the origin and target to the entrances of their clusters.
+        let mut extra = Graph::new();
+        let (mut pathfinder, offset) = self.cluster_pathfinder(self.cluster_of(origin));
+        for entrance in self.cluster_entrances(self.cluster_of(origin)) {
+            let result = pathfinder.find_path(sub(origin, offset), sub(entrance, offset));
+            nodes_expanded += result.nodes_expanded;
+            if result.found() {
+                extra
+                    .entry(origin)
+                    .or_insert_with(Vec::new)
+                    .push((entrance, result.cost));
+            }
+        }
+        if self.cluster_of(origin) == self.cluster_of(target) {
+            let result = pathfinder.find_path(sub(origin, offset), sub(target, offset));
+            nodes_expanded += result.nodes_expanded;
+            if result.found() {
+                extra
+                    .entry(origin)
+                    .or_insert_with(Vec::new)
+                    .push((target, result.cost));
+            }
+        }
+        let (mut pathfinder, offset) = self.cluster_pathfinder(self.cluster_of(target));
+        for entrance in self.cluster_entrances(self.cluster_of(target)) {
+            let result = pathfinder.find_path(sub(entrance, offset), sub(target, offset));
+            nodes_expanded += result.nodes_expanded;
+            if result.found() {
+                extra
+                    .entry(entrance)
+                    .or_insert_with(Vec::new)
+                    .push((target, result.cost));
+            }
+        }
+
+        // Search the abstract graph.
+        let mut costs: HashMap<Position, Distance> = HashMap::new();
+        let mut parents: HashMap<Position, Position> = HashMap::new();
+        let mut frontier = BinaryHeap::new();
+        let mut frontier_peak = 1;
+        costs.insert(origin, 0);
+        frontier.push(Reverse((self.map.heuristic(origin, target), 0, origin)));
+
+        let mut found = origin == target;
+        while let Some(Reverse((_, cost, node))) = frontier.pop() {
+            if costs[&node] < cost {
+                continue;
+            }
+            nodes_expanded += 1;
+            if node == target {
+                found = true;
+                break;
+            }
+
+            let edges = self.graph.get(&node).into_iter().flatten();
+            for (next, edge_cost) in edges.chain(extra.get(&node).into_iter().flatten()) {
+                let next_cost = cost + edge_cost;
+                if costs.get(next).map_or(true, |known| next_cost < *known) {
+                    costs.insert(*next, next_cost);
+                    parents.insert(*next, node);
+                    let priority = next_cost + self.map.heuristic(*next, target);
+                    frontier.push(Reverse((priority, next_cost, *next)));
+                }
+            }
+            frontier_peak = frontier_peak.max(frontier.len());
+        }
+
+        if !found {
+            return unreachable(nodes_expanded, frontier_peak);
+        }
+
+        let mut abstract_path = vec![target];
+        while let Some(parent) = parents.get(abstract_path.last().unwrap()) {
+            abstract_path.push(*parent);
+        }
+        abstract_path.reverse();
+
+        // Refine each abstract step into cells.
+        let mut path = vec![origin];
+        for pair in abstract_path.windows(2) {
+            let (from, to) = (pair[0], pair[1]);
+            if self.cluster_of(from) == self.cluster_of(to) {
+                let (mut pathfinder, offset) = self.cluster_pathfinder(self.cluster_of(from));
+                let result = pathfinder.find_path(sub(from, offset), sub(to, offset));
+                nodes_expanded += result.nodes_expanded;
+                path.extend(result.path.iter().skip(1).map(|p| add(*p, offset)));
+            } else {
+                path.push(to);
+            }
+        }
+
+        PathResult {
+            outcome: Some(PathOutcome::Found),
+            path,
+            cost: costs[&target],
+            nodes_expanded,
+            frontier_peak,
+        }
+    }
+
+    fn cluster_of(&self, position: Position) -> Position {
+        (
+            position.0 / self.cluster_size,
+            position.1 / self.cluster_size,
+        )
+    }
+
+    /// The first cell of a cluster and the cell after its last, on each axis.
+    fn cluster_bounds(&self, cluster: Position) -> (Position, Position) {
+        let (width, height) = self.map.len();
+        let min = (cluster.0 * self.cluster_size, cluster.1 * self.cluster_size);
+        let max = (
+            (min.0 + self.cluster_size).min(width),
+            (min.1 + self.cluster_size).min(height),
+        );
+        (min, max)
+    }
+
+    /// Finds the transitions from a cluster across its right (side 0) or
+    /// bottom (side 1) border. Each entrance is a run of cell pairs that are
+    /// passable on both sides, and gets a transition in its middle, or at both
+    /// ends if it's long.
+    fn find_transitions(&self, cluster: Position, side: usize) -> Vec<(Position, Position)> {
+        let (min, max) = self.cluster_bounds(cluster);
+        let (width, height) = self.map.len();
+        let pairs: Vec<(Position, Position)> = if side == 0 {
+            if max.0 >= width {
+                return Vec::new();
+            }
+            (min.1..max.1)
+                .map(|y| ((max.0 - 1, y), (max.0, y)))
+                .collect()
+        } else {
+            if max.1 >= height {
+                return Vec::new();
+            }
+            (min.0..max.0)
+                .map(|x| ((x, max.1 - 1), (x, max.1)))
+                .collect()
+        };
+
+        let mut transitions = Vec::new();
+        let mut run: Vec<(Position, Position)> = Vec::new();
+        for pair in pairs.into_iter().map(Some).chain(Some(None)) {
+            match pair {
+                Some((a, b)) if self.map.passable(a) && self.map.passable(b) => run.push((a, b)),
+                _ => {
+                    if run.len() >= LONG_ENTRANCE {
+                        transitions.push(run[0]);
+                        transitions.push(run[run.len() - 1]);
+                    } else if !run.is_empty() {
+                        transitions.push(run[run.len() / 2]);
+                    }
+                    run.clear();
+                }
+            }
+        }
+        transitions
+    }
+
+    /// The cells of a cluster that are on its side of a transition.
+    fn cluster_entrances(&self, cluster: Position) -> Vec<Position> {
+        let mut entrances: Vec<Position> = Vec::new();
+        for side in 0..2 {
+            entrances.extend(self.transitions[cluster][side].iter().map(|(a, _)| *a));
+        }
+        if cluster.0 > 0 {
+            let left = (cluster.0 - 1, cluster.1);
+            entrances.extend(self.transitions[left][0].iter().map(|(_, b)| *b));
+        }
+        if cluster.1 > 0 {
+            let above = (cluster.0, cluster.1 - 1);
+            entrances.extend(self.transitions[above][1].iter().map(|(_, b)| *b));
+        }
+        entrances.sort();
+        entrances.dedup();
+        entrances
+    }
+
+    /// A pathfinder over a copy of just one cluster, and the offset of the
+    /// cluster's first cell. Diagonal steps within a cluster only squeeze past
+    /// cells in the same cluster, so its paths are the same as on the full map.
+    fn cluster_pathfinder(&self, cluster: Position) -> (AStarPathfinder, Position) {
+        let (min, max) = self.cluster_bounds(cluster);
+        let mut map = Map::new(max.0 - min.0, max.1 - min.1, self.map.geometry());
+        map.set_costs(*self.map.costs());
+        for x in min.0..max.0 {
+            for y in min.1..max.1 {
+                map.set_terrain((x - min.0, y - min.1), self.map.terrain()[(x, y)]);
+            }
+        }
+        (
+            AStarPathfinder::new(map, (0, 0), (0, 0), SearchStrategy::AStar),
+            min,
+        )
+    }
+
+    /// Computes the cheapest cost between each pair of a cluster's entrances.
+    fn build_cluster(&mut self, cluster: Position) -> Cluster {
+        self.clusters_built += 1;
+        let entrances = self.cluster_entrances(cluster);
+        let (mut pathfinder, offset) = self.cluster_pathfinder(cluster);
+        let mut edges = Vec::new();
+        for from in entrances.iter() {
+            for to in entrances.iter() {
+                if from != to {
+                    let result = pathfinder.find_path(sub(*from, offset), sub(*to, offset));
+                    if result.found() {
+                        edges.push((*from, *to, result.cost));
+                    }
+                }
+            }
+        }
+        Cluster { edges }
+    }
+
+    /// Collects every cluster's edges and the transitions between them into the abstract graph.
+    fn build_graph(&mut self) {
+        let mut graph = Graph::new();
+        for (_, cluster) in self.clusters.iter() {
+            for (from, to, cost) in cluster.edges.iter() {
+                graph
+                    .entry(*from)
+                    .or_insert_with(Vec::new)
+                    .push((*to, *cost));
+            }
+        }
+        for (_, sides) in self.transitions.iter() {
+            for (a, b) in sides.iter().flatten() {
+                for (from, to) in [(*a, *b), (*b, *a)].iter() {
+                    if let Some(cost) = self.map.step_cost(*from, *to) {
+                        graph
+                            .entry(*from)
+                            .or_insert_with(Vec::new)
+                            .push((*to, cost));
+                    }
+                }
+            }
+        }
+        self.graph = graph;
+    }
+}
+
+fn add(a: Position, b: Position) -> Position {
+    (a.0 + b.0, a.1 + b.1)
+}
+
+fn sub(a: Position, b: Position) -> Position {
+    (a.0 - b.0, a.1 - b.1)
+}
+
+#[test]
+fn test_hierarchical_paths_are_valid_and_near_optimal() {
+    let mut total_cost = 0;
+    let mut total_optimal_cost = 0;
+    for seed in 0..6 {
+        for geometry in [Geometry::Square8, Geometry::Square4].iter() {
+            // Mixed terrain, with walls, forests and roads among the grass.
+            let mut rng = generators::rng_from_seed(seed);
+            let open = [
+                Terrain::Grass,
+                Terrain::Grass,
+                Terrain::Grass,
+                Terrain::Forest,
+                Terrain::Road,
+            ];
+            let map = generators::random_map(40, *geometry, 0.2, &open, &mut rng);
+            let mut hierarchy = Hierarchy::new(map.clone(), 8).unwrap();
+            let mut astar =
+                AStarPathfinder::new(map.clone(), (0, 0), (0, 0), SearchStrategy::AStar);
+
+            for _ in 0..10 {
+                let origin = (rng.gen_range(0, 40), rng.gen_range(0, 40));
+                let target = (rng.gen_range(0, 40), rng.gen_range(0, 40));
+                if !map.passable(origin) || !map.passable(target) {
+                    continue;
+                }
+                let expected = astar.find_path(origin, target);
+                let result = hierarchy.find_path(origin, target);
+                assert_eq!(result.outcome, expected.outcome, "seed {}", seed);
+                if !result.found() {
+                    continue;
+                }
+
+                assert_eq!(result.path.first(), Some(&origin));
+                assert_eq!(result.path.last(), Some(&target));
+                let cost: Distance = result
+                    .path
+                    .windows(2)
+                    .map(|pair| map.step_cost(pair[0], pair[1]).unwrap())
+                    .sum();
+                assert_eq!(cost, result.cost);
+                assert!(result.cost >= expected.cost);
+                total_cost += result.cost;
+                total_optimal_cost += expected.cost;
+            }
+        }
+    }
+
+    // Short paths can be much worse when they have to detour through an
+    // entrance, but on the whole they should be close.
+    assert!(
+        total_cost * 10 <= total_optimal_cost * 11,
+        "{} is far from optimal {}",
+        total_cost,
+        total_optimal_cost
+    );
+}
+
+#[test]
+fn test_terrain_changes_only_rebuild_affected_clusters() {
+    let mut hierarchy = Hierarchy::new(Map::new(32, 32, Geometry::Square8), 8).unwrap();
+    assert_eq!(hierarchy.clusters_built(), 16);
+
+    // A cell in the middle of a cluster only affects that cluster.
+    hierarchy.set_terrain((12, 12), Terrain::Wall);
+    assert_eq!(hierarchy.clusters_built(), 17);
+
+    // A cell on a border splits the entrance, which affects the cluster across it.
+    hierarchy.set_terrain((15, 12), Terrain::Wall);
+    assert_eq!(hierarchy.clusters_built(), 19);
+
+    // Changing it again doesn't move the entrances.
+    hierarchy.set_terrain((15, 12), Terrain::Water);
+    assert_eq!(hierarchy.clusters_built(), 20);
+
+    let fresh = Hierarchy::new(hierarchy.map().clone(), 8).unwrap();
+    assert!(fresh.clusters.iter().eq(hierarchy.clusters.iter()));
+    assert!(fresh.transitions.iter().eq(hierarchy.transitions.iter()));
+}
+
+#[test]
+fn test_hierarchy_rejects_hex_maps() {
+    assert!(Hierarchy::new(Map::new(16, 16, Geometry::Hex), 4).is_none());
+}
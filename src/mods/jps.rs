@@ -0,0 +1,421 @@
+//! Jump point search, which finds paths as cheap as A*'s on 8-way grids where
+//! every passable cell costs the same, while only expanding the cells where an
+//! optimal path might have to turn.
+//!
+//! Diagonal steps follow the map's rule for corners. Where they can't cut the
+//! corners of impassable cells, a diagonal move never has forced neighbours
+//! and every jump point is found by a straight scan. Where they can, a wall
+//! beside a diagonal move can force a neighbour too.
+
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+use super::map::Map;
+use super::pathfinding::{Array2D, Distance, PathOutcome, PathResult, Position, SearchState};
+use super::topology::{Geometry, Square8, Topology};
+#[cfg(test)]
+use super::{
+    generators, pathfinding::AStarPathfinder, pathfinding::SearchStrategy, terrain::Terrain,
+};
+
+/// A unit step in one of the eight directions.
+type Direction = (isize, isize);
+
+/// The four straight directions, in the order they're stored in a [JumpTable].
+const STRAIGHT: [Direction; 4] = [(0, -1), (1, 0), (0, 1), (-1, 0)];
+/// The four diagonal directions.
+const DIAGONAL: [Direction; 4] = [(1, -1), (1, 1), (-1, 1), (-1, -1)];
+
+/// Where a straight scan from a cell ends.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Jump {
+    /// The scan runs into a wall or the edge of the map after this many open cells.
+    Wall(Distance),
+    /// The scan reaches a jump point this many cells away.
+    Point(Distance),
+}
+
+impl Default for Jump {
+    fn default() -> Self {
+        Jump::Wall(0)
+    }
+}
+
+/// The precomputed result of a straight scan in each direction from each cell,
+/// which is what JPS+ adds to plain jump point search.
+type JumpTable = Array2D<[Jump; 4]>;
+
+/// Searches a uniform-cost 8-way map by jumping between jump points.
+pub struct JumpPointSearch {
+    /// The grid we're searching.
+    map: Map,
+    /// The cost multiplier shared by every passable cell.
+    unit_cost: Distance,
+    /// Precomputed straight scans, if this is JPS+.
+    table: Option<JumpTable>,
+    /// The jump points expanded by the last search, and the ones they were reached from.
+    search: SearchState,
+}
+
+impl JumpPointSearch {
+    /// Prepares a search over a map, or returns `None` if the map doesn't use
+    /// 8-way movement or its passable cells don't all cost the same. With
+    /// `preprocess`, the result of every straight scan is computed up front
+    /// (JPS+), which makes each search faster but the map slower to prepare.
+    pub fn new(map: Map, preprocess: bool) -> Option<Self> {
+        if map.geometry() != Geometry::Square8 {
+            return None;
+        }
+        let unit_cost = map.uniform_cost()?;
+        let search = SearchState::new(map.len());
+        let mut self_ = Self {
+            map,
+            unit_cost,
+            table: None,
+            search,
+        };
+        if preprocess {
+            self_.table = Some(self_.build_table());
+        }
+        Some(self_)
+    }
+
+    pub fn map(&self) -> &Map {
+        &self.map
+    }
+
+    pub fn preprocessed(&self) -> bool {
+        self.table.is_some()
+    }
+
+    /// The jump points expanded by the last search.
+    pub fn search(&self) -> &SearchState {
+        &self.search
+    }
+
+    /// Finds a cheapest path from `origin` to `target`, including every cell
+    /// between the jump points on it.
+    pub fn find_path(&mut self, origin: Position, target: Position) -> PathResult {
+        self.search.reset();
+        self.search.relax(origin, origin, 0);
+
+        let mut frontier = BinaryHeap::new();
+        frontier.push(Reverse((self.heuristic(origin, target), 0, origin, origin)));
+        let mut nodes_expanded = 0;
+        let mut frontier_peak = 1;
+
+        while let Some(Reverse((_, cost, position, parent))) = frontier.pop() {
+            if self.search.closed(position) {
+                continue;
+            }
+            self.search.close(position, parent, cost);
+            nodes_expanded += 1;
+
+            if position == target {
+                return PathResult {
+                    outcome: Some(PathOutcome::Found),
+                    path: self.path_to(origin, target),
+                    cost,
+                    nodes_expanded,
+                    frontier_peak,
+                };
+            }
+
+            for direction in self.directions(position, parent) {
+                let jump_point = if direction.0 != 0 && direction.1 != 0 {
+                    self.jump_diagonal(position, direction, target)
+                } else {
+                    self.jump_straight(position, direction, target)
+                };
+                if let Some(jump_point) = jump_point {
+                    let jump_cost = cost + Square8.heuristic(position, jump_point) * self.unit_cost;
+                    if self.search.relax(jump_point, position, jump_cost) {
+                        let priority = jump_cost + self.heuristic(jump_point, target);
+                        frontier.push(Reverse((priority, jump_cost, jump_point, position)));
+                    }
+                }
+            }
+            frontier_peak = frontier_peak.max(frontier.len());
+        }
+
+        PathResult {
+            outcome: Some(PathOutcome::Unreachable),
+            path: Vec::new(),
+            cost: 0,
+            nodes_expanded,
+            frontier_peak,
+        }
+    }
+
+    fn heuristic(&self, a: Position, b: Position) -> Distance {
+        Square8.heuristic(a, b) * self.unit_cost
+    }
+
+    /// Whether a cell exists and is passable.
+    fn open(&self, x: isize, y: isize) -> bool {
+        x >= 0
+            && y >= 0
+            && self.map.contains((x as Distance, y as Distance))
+            && self.map.passable((x as Distance, y as Distance))
+    }
+
+    /// The directions worth exploring from a jump point, given the one it was reached from.
+    fn directions(&self, position: Position, parent: Position) -> Vec<Direction> {
+        if position == parent {
+            return STRAIGHT.iter().chain(DIAGONAL.iter()).cloned().collect();
+        }
+        let (dx, dy) = direction(parent, position);
+        if dx != 0 && dy != 0 {
+            if self.map.costs().cut_corners() {
+                vec![(dx, 0), (0, dy), (dx, dy), (-dx, dy), (dx, -dy)]
+            } else {
+                vec![(dx, 0), (0, dy), (dx, dy)]
+            }
+        } else if dx != 0 {
+            vec![(dx, 0), (dx, -1), (dx, 1), (0, -1), (0, 1)]
+        } else {
+            vec![(0, dy), (-1, dy), (1, dy), (-1, 0), (1, 0)]
+        }
+    }
+
+    /// Whether a cell reached by moving in `direction` has a neighbour that
+    /// could only be reached optimally through it.
+    fn forced(&self, (x, y): (isize, isize), (dx, dy): Direction) -> bool {
+        let cut_corners = self.map.costs().cut_corners();
+        if dx != 0 && dy != 0 {
+            // Without cutting corners, both cells beside a diagonal step are
+            // open, so anything past them can be reached around them.
+            cut_corners
+                && ((self.open(x - dx, y + dy) && !self.open(x - dx, y))
+                    || (self.open(x + dx, y - dy) && !self.open(x, y - dy)))
+        } else {
+            let (px, py) = (dy.abs(), dx.abs());
+            [-1, 1].iter().any(|side| {
+                let (sx, sy) = (x + px * side, y + py * side);
+                if cut_corners {
+                    self.open(sx + dx, sy + dy) && !self.open(sx, sy)
+                } else {
+                    self.open(sx, sy) && !self.open(sx - dx, sy - dy)
+                }
+            })
+        }
+    }
+
+    /// Scans straight from `from` for the target or the next jump point.
+    fn jump_straight(
+        &self,
+        from: Position,
+        direction: Direction,
+        target: Position,
+    ) -> Option<Position> {
+        let (dx, dy) = direction;
+        let (x, y) = (from.0 as isize, from.1 as isize);
+
+        if let Some(table) = &self.table {
+            let index = STRAIGHT.iter().position(|d| *d == direction).unwrap();
+            let (distance, point) = match table[from][index] {
+                Jump::Wall(distance) => (distance as isize, false),
+                Jump::Point(distance) => (distance as isize, true),
+            };
+            let (tx, ty) = (target.0 as isize, target.1 as isize);
+            let along = (tx - x) * dx + (ty - y) * dy;
+            let on_line = if dx != 0 { ty == y } else { tx == x };
+            if on_line && along > 0 && along <= distance {
+                return Some(target);
+            }
+            return if point {
+                Some((
+                    (x + dx * distance) as Distance,
+                    (y + dy * distance) as Distance,
+                ))
+            } else {
+                None
+            };
+        }
+
+        let (mut x, mut y) = (x, y);
+        loop {
+            x += dx;
+            y += dy;
+            if !self.open(x, y) {
+                return None;
+            }
+            let position = (x as Distance, y as Distance);
+            if position == target || self.forced((x, y), direction) {
+                return Some(position);
+            }
+        }
+    }
+
+    /// Scans diagonally from `from` for the target, a jump point, or a cell
+    /// that a straight scan would find something from.
+    fn jump_diagonal(
+        &self,
+        from: Position,
+        direction: Direction,
+        target: Position,
+    ) -> Option<Position> {
+        let (dx, dy) = direction;
+        let cut_corners = self.map.costs().cut_corners();
+        let (mut x, mut y) = (from.0 as isize, from.1 as isize);
+        loop {
+            if !(self.open(x + dx, y + dy)
+                && (cut_corners || self.open(x + dx, y) && self.open(x, y + dy)))
+            {
+                return None;
+            }
+            x += dx;
+            y += dy;
+            let position = (x as Distance, y as Distance);
+            if position == target
+                || self.forced((x, y), direction)
+                || self.jump_straight(position, (dx, 0), target).is_some()
+                || self.jump_straight(position, (0, dy), target).is_some()
+            {
+                return Some(position);
+            }
+        }
+    }
+
+    /// Computes every straight scan, sweeping against each direction so each
+    /// cell's scan can extend its neighbour's.
+    fn build_table(&self) -> JumpTable {
+        let (width, height) = self.map.len();
+        let mut table = JumpTable::new(width, height);
+        for (index, direction) in STRAIGHT.iter().enumerate() {
+            let (dx, dy) = *direction;
+            let xs: Vec<Distance> = if dx > 0 {
+                (0..width).rev().collect()
+            } else {
+                (0..width).collect()
+            };
+            let ys: Vec<Distance> = if dy > 0 {
+                (0..height).rev().collect()
+            } else {
+                (0..height).collect()
+            };
+            for y in ys.iter() {
+                for x in xs.iter() {
+                    let next = (*x as isize + dx, *y as isize + dy);
+                    table[(*x, *y)][index] = if !self.open(next.0, next.1) {
+                        Jump::Wall(0)
+                    } else if self.forced(next, *direction) {
+                        Jump::Point(1)
+                    } else {
+                        match table[(next.0 as Distance, next.1 as Distance)][index] {
+                            Jump::Wall(distance) => Jump::Wall(distance + 1),
+                            Jump::Point(distance) => Jump::Point(distance + 1),
+                        }
+                    };
+                }
+            }
+        }
+        table
+    }
+
+    /// Follows the expanded jump points back from `target`, filling in the cells between them.
+    fn path_to(&self, origin: Position, target: Position) -> Vec<Position> {
+        let mut path = vec![target];
+        let mut current = target;
+        while current != origin {
+            let parent = self.search.parent(current).unwrap();
+            let (dx, dy) = direction(current, parent);
+            while current != parent {
+                current = (
+                    (current.0 as isize + dx) as Distance,
+                    (current.1 as isize + dy) as Distance,
+                );
+                path.push(current);
+            }
+        }
+        path.reverse();
+        path
+    }
+}
+
+/// The unit step that leads from one cell towards another on the same row,
+/// column or diagonal.
+fn direction(from: Position, to: Position) -> Direction {
+    (
+        (to.0 as isize - from.0 as isize).signum(),
+        (to.1 as isize - from.1 as isize).signum(),
+    )
+}
+
+#[test]
+fn test_jump_point_paths_cost_the_same_as_astar() {
+    for seed in 0..80 {
+        let density = 0.1 + (seed % 4) as f64 * 0.1;
+        let mut rng = generators::rng_from_seed(seed);
+        let mut map =
+            generators::random_map(24, Geometry::Square8, density, &[Terrain::Grass], &mut rng);
+        let mut costs = *map.costs();
+        costs.set_cut_corners(seed < 40);
+        map.set_costs(costs);
+        let mut astar = AStarPathfinder::new(map.clone(), (0, 0), (0, 0), SearchStrategy::AStar);
+        let mut jps = JumpPointSearch::new(map.clone(), false).unwrap();
+        let mut jps_plus = JumpPointSearch::new(map.clone(), true).unwrap();
+
+        for target in [(23, 23), (23, 0), (0, 23), (12, 11), (5, 17)].iter() {
+            if !map.passable(*target) {
+                continue;
+            }
+            let expected = astar.find_path((0, 0), *target);
+            for search in [&mut jps, &mut jps_plus].iter_mut() {
+                let result = search.find_path((0, 0), *target);
+                assert_eq!(result.outcome, expected.outcome, "seed {}", seed);
+                assert_eq!(result.cost, expected.cost, "seed {} to {:?}", seed, target);
+                if result.found() {
+                    assert_eq!(result.path.first(), Some(&(0, 0)));
+                    assert_eq!(result.path.last(), Some(target));
+                    let cost: Distance = result
+                        .path
+                        .windows(2)
+                        .map(|pair| map.step_cost(pair[0], pair[1]).unwrap())
+                        .sum();
+                    assert_eq!(cost, result.cost);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_jump_point_search_needs_uniform_8_way_maps() {
+    let mut map = Map::new(8, 8, Geometry::Square8);
+    assert!(JumpPointSearch::new(map.clone(), true).is_some());
+    map.set_terrain((3, 3), Terrain::Wall);
+    assert!(JumpPointSearch::new(map.clone(), true).is_some());
+    map.set_terrain((4, 4), Terrain::Forest);
+    assert!(JumpPointSearch::new(map.clone(), true).is_none());
+    map.set_terrain((4, 4), Terrain::Grass);
+    map.set_geometry(Geometry::Hex);
+    assert!(JumpPointSearch::new(map, true).is_none());
+}
+
+/// Checks JPS and JPS+ against A* on a large, mostly open map, where jumps
+/// run much further than on the small maps above. It's slow without
+/// optimisations, so run it with `cargo test --release -- --ignored`. The
+/// `bench` command compares their speed on scenario files.
+#[test]
+#[ignore]
+fn test_jump_point_search_on_a_large_map() {
+    let mut rng = generators::rng_from_seed(7);
+    let map = generators::random_map(512, Geometry::Square8, 0.05, &[Terrain::Grass], &mut rng);
+    let queries: Vec<(Position, Position)> = (0..20)
+        .map(|i| ((0, 0), (511 - i * 7, 511 - (i * 13) % 500)))
+        .filter(|(_, target)| map.passable(*target))
+        .collect();
+
+    let mut astar = AStarPathfinder::new(map.clone(), (0, 0), (0, 0), SearchStrategy::AStar);
+    let expected: Vec<PathResult> = queries
+        .iter()
+        .map(|(origin, target)| astar.find_path(*origin, *target))
+        .collect();
+
+    for preprocess in [false, true].iter() {
+        let mut jps = JumpPointSearch::new(map.clone(), *preprocess).unwrap();
+        for ((origin, target), expected) in queries.iter().zip(expected.iter()) {
+            assert_eq!(jps.find_path(*origin, *target).cost, expected.cost);
+        }
+    }
+}
@@ -0,0 +1,178 @@
+use serde_derive::{Deserialize, Serialize};
+
+use super::pathfinding::{Array2D, Distance, Position};
+use super::terrain::{Terrain, TerrainCosts};
+use super::topology::{Geometry, Topology};
+
+/// The static data for a grid: its shape, the terrain of each cell, and the
+/// cost of moving through each type of terrain. A single map can be searched
+/// any number of times.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Map {
+    /// The shape of the grid.
+    geometry: Geometry,
+    /// The cost of moving into each type of terrain.
+    costs: TerrainCosts,
+    /// The terrain of each cell.
+    terrain: Array2D<Terrain>,
+}
+
+impl Map {
+    /// Constructs a map filled with the default terrain.
+    pub fn new(width: Distance, height: Distance, geometry: Geometry) -> Self {
+        Self {
+            geometry,
+            costs: TerrainCosts::default(),
+            terrain: Array2D::new(width, height),
+        }
+    }
+
+    pub fn width(&self) -> Distance {
+        self.terrain.len().0
+    }
+
+    pub fn height(&self) -> Distance {
+        self.terrain.len().1
+    }
+
+    pub fn len(&self) -> Position {
+        self.terrain.len()
+    }
+
+    /// Whether a position is within the bounds of the map.
+    pub fn contains(&self, position: Position) -> bool {
+        position.0 < self.width() && position.1 < self.height()
+    }
+
+    pub fn geometry(&self) -> Geometry {
+        self.geometry
+    }
+
+    pub fn set_geometry(&mut self, geometry: Geometry) {
+        self.geometry = geometry;
+    }
+
+    pub fn topology(&self) -> &'static dyn Topology {
+        self.geometry.topology()
+    }
+
+    pub fn costs(&self) -> &TerrainCosts {
+        &self.costs
+    }
+
+    pub fn set_costs(&mut self, costs: TerrainCosts) {
+        self.costs = costs;
+    }
+
+    pub fn terrain(&self) -> &Array2D<Terrain> {
+        &self.terrain
+    }
+
+    pub fn set_terrain(&mut self, position: Position, terrain: Terrain) {
+        self.terrain[position] = terrain;
+    }
+
+    /// Whether a cell can be entered at all.
+    pub fn passable(&self, position: Position) -> bool {
+        self.costs.passable(self.terrain[position])
+    }
+
+    /// The cells adjacent to a position, whether or not they're passable.
+    pub fn neighbours(&self, position: Position) -> Vec<Position> {
+        self.topology().neighbours(position, self.len())
+    }
+
+    /// The cost of stepping between two adjacent cells, or `None` if `to` is
+    /// impassable or the step cuts the corner of an impassable cell on a map
+    /// whose costs don't allow that.
+    pub fn step_cost(&self, from: Position, to: Position) -> Option<Distance> {
+        let topology = self.topology();
+        if !self.costs.cut_corners()
+            && !topology
+                .corners(from, to)
+                .into_iter()
+                .all(|corner| self.passable(corner))
+        {
+            return None;
+        }
+        self.costs
+            .get(self.terrain[to])
+            .map(|cost| cost * topology.step_cost(from, to))
+    }
+
+    /// A lower bound on the cost of getting from `a` to `b`, assuming the cheapest terrain throughout.
+    pub fn heuristic(&self, a: Position, b: Position) -> Distance {
+        self.topology().heuristic(a, b) * self.costs.min_cost()
+    }
+
+    /// The cost multiplier shared by every passable cell, if they all share one.
+    pub fn uniform_cost(&self) -> Option<Distance> {
+        let mut costs = self
+            .terrain
+            .iter()
+            .filter_map(|(_, terrain)| self.costs.get(*terrain));
+        let first = costs.next().unwrap_or_else(|| self.costs.min_cost());
+        if costs.all(|cost| cost == first) {
+            Some(first)
+        } else {
+            None
+        }
+    }
+
+    /// Generates one of a family of 64×64 demo maps, with a clear corner at
+    /// (1, 1) and (62, 62) for the origin and target.
+    pub fn demo(demo_iteration: usize, geometry: Geometry) -> Self {
+        let mut map = Self::new(64, 64, geometry);
+        let (width, height) = map.len();
+
+        for x in 0..width {
+            for y in 0..height {
+                if x + y < 5 {
+                    continue;
+                }
+
+                if (width - x) + (height - y) < 5 {
+                    continue;
+                }
+
+                let mut terrain = match ((x / 6) * 7 + (y / 6) * 3 + demo_iteration) % 7 {
+                    1 | 4 => Terrain::Forest,
+                    2 => Terrain::Mountain,
+                    5 => Terrain::Water,
+                    _ => Terrain::Grass,
+                };
+
+                if x % 16 == 8 || y % 16 == 12 {
+                    terrain = Terrain::Road;
+                }
+
+                if ((x) ^ ((y + 3) % 2) ^ demo_iteration) % (1 + x / 2 + y / 5) <= 0 {
+                    terrain = Terrain::Wall;
+                }
+
+                if ((x / 8) + ((y * 3) / 8)) % 5 == 0 {
+                    terrain = Terrain::Wall;
+                }
+
+                map.set_terrain((x, y), terrain);
+            }
+        }
+
+        map
+    }
+}
+
+#[test]
+fn test_diagonal_steps_only_cut_corners_if_allowed() {
+    let mut map = Map::new(3, 3, Geometry::Square8);
+    map.set_terrain((1, 0), Terrain::Wall);
+    assert!(map.step_cost((0, 0), (1, 1)).is_some());
+
+    map.set_costs(TerrainCosts::moving_ai());
+    assert_eq!(map.step_cost((0, 0), (1, 1)), None);
+    assert_eq!(map.step_cost((1, 1), (2, 0)), None);
+    assert!(map.step_cost((0, 1), (1, 2)).is_some());
+
+    map.set_geometry(Geometry::Hex);
+    assert!(map.step_cost((0, 2), (1, 1)).is_some());
+}
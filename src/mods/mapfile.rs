@@ -0,0 +1,525 @@
+//! Reading and writing maps as text.
+//!
+//! Two formats are supported:
+//!
+//! - The Moving AI Lab's benchmark `.map` format, along with its `.scen`
+//!   scenario files listing origin/target pairs and their optimal lengths.
+//! - A simple ASCII format, with one character per cell and the origin and
+//!   target marked with `S` and `G`, or given on header lines if they aren't
+//!   on grass.
+
+use std::{error, fmt, fs, io, path::Path};
+
+use super::map::Map;
+use super::pathfinding::{AStarPathfinder, Position, SearchStrategy};
+use super::terrain::{Terrain, TerrainCosts};
+use super::topology::Geometry;
+
+/// A map read from a file, along with the origin and target if the file had them.
+#[derive(Clone)]
+pub struct MapFile {
+    pub map: Map,
+    pub origin: Option<Position>,
+    pub target: Option<Position>,
+}
+
+impl MapFile {
+    /// Constructs a pathfinder from the origin to the target, if the file had both.
+    pub fn into_pathfinder(self, strategy: SearchStrategy) -> Option<AStarPathfinder> {
+        match (self.origin, self.target) {
+            (Some(origin), Some(target)) => {
+                Some(AStarPathfinder::new(self.map, origin, target, strategy))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// One line of a Moving AI `.scen` file: a single pathfinding problem.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Scenario {
+    /// Problems are grouped into buckets of similar difficulty.
+    pub bucket: usize,
+    /// The `.map` file this problem is on, relative to the scenario file.
+    pub map: String,
+    /// The size of the map.
+    pub size: Position,
+    pub origin: Position,
+    pub target: Position,
+    /// The length of an optimal path, counting diagonal steps as √2.
+    pub optimal_length: f64,
+}
+
+#[derive(Debug)]
+pub enum MapFileError {
+    Io(io::Error),
+    /// The file's contents couldn't be understood. Lines are numbered from 1.
+    Parse {
+        line: usize,
+        message: String,
+    },
+}
+
+impl MapFileError {
+    fn parse(line: usize, message: impl Into<String>) -> Self {
+        MapFileError::Parse {
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for MapFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapFileError::Io(error) => write!(f, "{}", error),
+            MapFileError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+        }
+    }
+}
+
+impl error::Error for MapFileError {}
+
+impl From<io::Error> for MapFileError {
+    fn from(error: io::Error) -> Self {
+        MapFileError::Io(error)
+    }
+}
+
+/// Loads a map from a file, as the Moving AI format if it has a `.map`
+/// extension and as the ASCII format otherwise.
+pub fn load(path: &Path) -> Result<MapFile, MapFileError> {
+    let text = fs::read_to_string(path)?;
+    if is_moving_ai(path) {
+        Ok(MapFile {
+            map: read_moving_ai(&text)?,
+            origin: None,
+            target: None,
+        })
+    } else {
+        read_ascii(&text)
+    }
+}
+
+/// Saves a map to a file, choosing the format by extension like [load]. The
+/// origin and target are only saved in the ASCII format.
+pub fn save(path: &Path, file: &MapFile) -> Result<(), MapFileError> {
+    let text = if is_moving_ai(path) {
+        write_moving_ai(&file.map)
+    } else {
+        write_ascii(&file.map, file.origin, file.target)
+    };
+    fs::write(path, text)?;
+    Ok(())
+}
+
+/// Loads a Moving AI `.scen` file.
+pub fn load_scenarios(path: &Path) -> Result<Vec<Scenario>, MapFileError> {
+    read_scenarios(&fs::read_to_string(path)?)
+}
+
+fn is_moving_ai(path: &Path) -> bool {
+    path.extension()
+        .map_or(false, |extension| extension == "map")
+}
+
+/// Parses a map in the Moving AI format, using the Moving AI movement rules.
+pub fn read_moving_ai(text: &str) -> Result<Map, MapFileError> {
+    let mut lines = text.lines().enumerate().map(|(i, line)| (i + 1, line));
+    let mut width = None;
+    let mut height = None;
+
+    loop {
+        let (number, line) = lines
+            .next()
+            .ok_or_else(|| MapFileError::parse(0, "missing \"map\" header line"))?;
+        let mut words = line.split_whitespace();
+        match (words.next(), words.next()) {
+            (Some("type"), Some("octile")) => {}
+            (Some("type"), kind) => {
+                return Err(MapFileError::parse(
+                    number,
+                    format!("unsupported map type {:?}", kind.unwrap_or("")),
+                ))
+            }
+            (Some("height"), Some(value)) => height = Some(parse_number(number, value)?),
+            (Some("width"), Some(value)) => width = Some(parse_number(number, value)?),
+            (Some("map"), None) => break,
+            (None, _) => {}
+            _ => return Err(MapFileError::parse(number, "unrecognized header line")),
+        }
+    }
+
+    let width = width.ok_or_else(|| MapFileError::parse(0, "missing width"))?;
+    let height = height.ok_or_else(|| MapFileError::parse(0, "missing height"))?;
+    let mut map = Map::new(width, height, Geometry::Square8);
+    map.set_costs(TerrainCosts::moving_ai());
+
+    for y in 0..height {
+        let (number, line) = lines
+            .next()
+            .ok_or_else(|| MapFileError::parse(0, format!("expected {} rows", height)))?;
+        read_row(&mut map, y, number, line, |c| match c {
+            '.' | 'G' => Some(Terrain::Grass),
+            '@' | 'O' => Some(Terrain::Wall),
+            'T' => Some(Terrain::Forest),
+            'S' => Some(Terrain::Swamp),
+            'W' => Some(Terrain::Water),
+            _ => None,
+        })?;
+    }
+
+    Ok(map)
+}
+
+/// Formats a map in the Moving AI format. Terrain the format can't represent
+/// is written as its closest equivalent.
+pub fn write_moving_ai(map: &Map) -> String {
+    let mut text = format!(
+        "type octile\nheight {}\nwidth {}\nmap\n",
+        map.height(),
+        map.width()
+    );
+    write_rows(&mut text, map, |terrain, _| match terrain {
+        Terrain::Road | Terrain::Grass => '.',
+        Terrain::Swamp => 'S',
+        Terrain::Forest => 'T',
+        Terrain::Water => 'W',
+        Terrain::Mountain | Terrain::Wall => '@',
+    });
+    text
+}
+
+/// Parses a map in the ASCII format: `.` for grass, `#` for walls, `S` for the
+/// origin and `G` for the target (both on grass), plus `=` for roads, `%` for
+/// swamps, `T` for forests, `^` for mountains and `~` for water. An origin or
+/// target on other terrain is given on a line such as `origin 3 4` before the
+/// rows instead.
+pub fn read_ascii(text: &str) -> Result<MapFile, MapFileError> {
+    let lines: Vec<(usize, &str)> = text
+        .lines()
+        .enumerate()
+        .map(|(i, line)| (i + 1, line.trim_end()))
+        .filter(|(_, line)| !line.is_empty())
+        .collect();
+    let headers = lines
+        .iter()
+        .take_while(|(_, line)| line.starts_with("origin ") || line.starts_with("target "))
+        .count();
+    let (headers, rows) = lines.split_at(headers);
+
+    let width = rows.first().map_or(0, |(_, line)| line.chars().count());
+    let mut file = MapFile {
+        map: Map::new(width, rows.len(), Geometry::Square8),
+        origin: None,
+        target: None,
+    };
+
+    for (y, (number, line)) in rows.iter().enumerate() {
+        read_row(&mut file.map, y, *number, line, |c| match c {
+            '.' | 'S' | 'G' => Some(Terrain::Grass),
+            '#' => Some(Terrain::Wall),
+            '=' => Some(Terrain::Road),
+            '%' => Some(Terrain::Swamp),
+            'T' => Some(Terrain::Forest),
+            '^' => Some(Terrain::Mountain),
+            '~' => Some(Terrain::Water),
+            _ => None,
+        })?;
+        for (x, c) in line.chars().enumerate() {
+            match c {
+                'S' => set_endpoint(&mut file.origin, "origin", (x, y), *number)?,
+                'G' => set_endpoint(&mut file.target, "target", (x, y), *number)?,
+                _ => {}
+            }
+        }
+    }
+
+    for (number, line) in headers {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let position = match words[..] {
+            [_, x, y] => (parse_number(*number, x)?, parse_number(*number, y)?),
+            _ => return Err(MapFileError::parse(*number, "expected an x and a y")),
+        };
+        if !file.map.contains(position) {
+            return Err(MapFileError::parse(*number, "outside the map"));
+        }
+        match words[0] {
+            "origin" => set_endpoint(&mut file.origin, "origin", position, *number)?,
+            _ => set_endpoint(&mut file.target, "target", position, *number)?,
+        }
+    }
+
+    Ok(file)
+}
+
+fn set_endpoint(
+    endpoint: &mut Option<Position>,
+    name: &str,
+    position: Position,
+    line: usize,
+) -> Result<(), MapFileError> {
+    if endpoint.is_some() {
+        return Err(MapFileError::parse(line, format!("more than one {}", name)));
+    }
+    *endpoint = Some(position);
+    Ok(())
+}
+
+/// Formats a map in the ASCII format, marking the origin and target if given.
+/// Either of them that isn't on grass is written on a header line, so that
+/// the terrain under it is kept.
+pub fn write_ascii(map: &Map, origin: Option<Position>, target: Option<Position>) -> String {
+    let on_grass = |position: Position| map.terrain()[position] == Terrain::Grass;
+    let origin = origin.filter(|&position| map.contains(position));
+    let target = target.filter(|&position| map.contains(position));
+
+    let mut text = String::new();
+    for (name, endpoint) in [("origin", origin), ("target", target)].iter() {
+        if let Some((x, y)) = endpoint.filter(|&position| !on_grass(position)) {
+            text.push_str(&format!("{} {} {}\n", name, x, y));
+        }
+    }
+    write_rows(&mut text, map, |terrain, position| {
+        if Some(position) == origin && on_grass(position) {
+            'S'
+        } else if Some(position) == target && on_grass(position) {
+            'G'
+        } else {
+            match terrain {
+                Terrain::Road => '=',
+                Terrain::Grass => '.',
+                Terrain::Swamp => '%',
+                Terrain::Forest => 'T',
+                Terrain::Mountain => '^',
+                Terrain::Water => '~',
+                Terrain::Wall => '#',
+            }
+        }
+    });
+    text
+}
+
+/// Parses a Moving AI `.scen` file.
+pub fn read_scenarios(text: &str) -> Result<Vec<Scenario>, MapFileError> {
+    let mut scenarios = Vec::new();
+
+    for (i, line) in text.lines().enumerate() {
+        let number = i + 1;
+        if line.trim().is_empty() || (number == 1 && line.starts_with("version")) {
+            continue;
+        }
+
+        let fields: Vec<&str> = line.split('\t').collect();
+        if fields.len() != 9 {
+            return Err(MapFileError::parse(
+                number,
+                format!("expected 9 tab-separated fields, found {}", fields.len()),
+            ));
+        }
+        let field = |i: usize| parse_number(number, fields[i]);
+
+        scenarios.push(Scenario {
+            bucket: field(0)?,
+            map: fields[1].to_string(),
+            size: (field(2)?, field(3)?),
+            origin: (field(4)?, field(5)?),
+            target: (field(6)?, field(7)?),
+            optimal_length: fields[8].trim().parse().map_err(|_| {
+                MapFileError::parse(number, format!("invalid length {:?}", fields[8]))
+            })?,
+        });
+    }
+
+    Ok(scenarios)
+}
+
+/// Formats scenarios as a Moving AI `.scen` file.
+pub fn write_scenarios(scenarios: &[Scenario]) -> String {
+    let mut text = String::from("version 1\n");
+    for scenario in scenarios {
+        text.push_str(&format!(
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{:.8}\n",
+            scenario.bucket,
+            scenario.map,
+            scenario.size.0,
+            scenario.size.1,
+            scenario.origin.0,
+            scenario.origin.1,
+            scenario.target.0,
+            scenario.target.1,
+            scenario.optimal_length
+        ));
+    }
+    text
+}
+
+fn parse_number(line: usize, value: &str) -> Result<usize, MapFileError> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| MapFileError::parse(line, format!("invalid number {:?}", value)))
+}
+
+/// Sets the terrain of row `y` from the characters of `line`.
+fn read_row(
+    map: &mut Map,
+    y: usize,
+    number: usize,
+    line: &str,
+    terrain: impl Fn(char) -> Option<Terrain>,
+) -> Result<(), MapFileError> {
+    let length = line.chars().count();
+    if length != map.width() {
+        return Err(MapFileError::parse(
+            number,
+            format!("expected {} cells, found {}", map.width(), length),
+        ));
+    }
+    for (x, c) in line.chars().enumerate() {
+        let terrain = terrain(c)
+            .ok_or_else(|| MapFileError::parse(number, format!("unknown cell {:?}", c)))?;
+        map.set_terrain((x, y), terrain);
+    }
+    Ok(())
+}
+
+fn write_rows(text: &mut String, map: &Map, cell: impl Fn(Terrain, Position) -> char) {
+    for y in 0..map.height() {
+        for x in 0..map.width() {
+            text.push(cell(map.terrain()[(x, y)], (x, y)));
+        }
+        text.push('\n');
+    }
+}
+
+#[test]
+fn test_ascii_round_trip() {
+    let text = "\
+S..#....
+.=.#.TT.
+.=...^^.
+%%~~...G
+";
+    let file = read_ascii(text).unwrap();
+    assert_eq!(file.map.len(), (8, 4));
+    assert_eq!(file.origin, Some((0, 0)));
+    assert_eq!(file.target, Some((7, 3)));
+    assert_eq!(file.map.terrain()[(3, 1)], Terrain::Wall);
+    assert_eq!(file.map.terrain()[(1, 2)], Terrain::Road);
+    assert_eq!(file.map.terrain()[(7, 3)], Terrain::Grass);
+    assert_eq!(write_ascii(&file.map, file.origin, file.target), text);
+
+    let mut pathfinder = file.into_pathfinder(SearchStrategy::AStar).unwrap();
+    assert_eq!(pathfinder.get_path().path.last(), Some(&(7, 3)));
+
+    // An endpoint on a road can't be marked without losing the road, so
+    // it's given on a header line instead.
+    let text = "\
+origin 1 2
+...#....
+.=.#.TT.
+.=...^^.
+%%~~...G
+";
+    let file = read_ascii(text).unwrap();
+    assert_eq!(file.origin, Some((1, 2)));
+    assert_eq!(file.map.terrain()[(1, 2)], Terrain::Road);
+    assert_eq!(write_ascii(&file.map, file.origin, file.target), text);
+
+    let mut map = file.map;
+    map.set_terrain((7, 3), Terrain::Road);
+    let file = read_ascii(&write_ascii(&map, Some((1, 2)), Some((7, 3)))).unwrap();
+    assert_eq!((file.origin, file.target), (Some((1, 2)), Some((7, 3))));
+    assert_eq!(
+        write_ascii(&file.map, None, None),
+        write_ascii(&map, None, None)
+    );
+}
+
+#[test]
+fn test_ascii_errors() {
+    match read_ascii("S..\n..\n") {
+        Err(MapFileError::Parse { line: 2, .. }) => {}
+        _ => panic!("ragged rows should be rejected"),
+    }
+    match read_ascii("S.S\n...\n") {
+        Err(MapFileError::Parse { line: 1, .. }) => {}
+        _ => panic!("multiple origins should be rejected"),
+    }
+    match read_ascii("S..\n.?G\n") {
+        Err(MapFileError::Parse { line: 2, .. }) => {}
+        _ => panic!("unknown cells should be rejected"),
+    }
+    match read_ascii("origin 0 0\nS..\n..G\n") {
+        Err(MapFileError::Parse { line: 1, .. }) => {}
+        _ => panic!("an origin given twice should be rejected"),
+    }
+    match read_ascii("target 3 1\nS..\n...\n") {
+        Err(MapFileError::Parse { line: 1, .. }) => {}
+        _ => panic!("endpoints outside the map should be rejected"),
+    }
+}
+
+#[test]
+fn test_moving_ai_round_trip() {
+    let text = "\
+type octile
+height 3
+width 5
+map
+..@@T
+.S.WW
+@@...
+";
+    let map = read_moving_ai(text).unwrap();
+    assert_eq!(map.len(), (5, 3));
+    assert_eq!(map.geometry(), Geometry::Square8);
+    assert_eq!(map.terrain()[(4, 0)], Terrain::Forest);
+    assert!(map.passable((1, 1)));
+    assert!(!map.passable((4, 0)));
+    assert!(!map.passable((3, 1)));
+    assert_eq!(write_moving_ai(&map), text);
+}
+
+#[test]
+fn test_moving_ai_errors() {
+    match read_moving_ai("type hex\nheight 1\nwidth 1\nmap\n.\n") {
+        Err(MapFileError::Parse { line: 1, .. }) => {}
+        _ => panic!("other map types should be rejected"),
+    }
+    match read_moving_ai("type octile\nheight 2\nwidth 2\nmap\n..\n") {
+        Err(MapFileError::Parse { .. }) => {}
+        _ => panic!("missing rows should be rejected"),
+    }
+}
+
+#[test]
+fn test_scenario_round_trip() {
+    let text = "\
+version 1
+0\tmaps/test.map\t5\t3\t1\t1\t4\t2\t3.41421356
+3\tmaps/test.map\t5\t3\t0\t0\t2\t2\t2.82842712
+";
+    let scenarios = read_scenarios(text).unwrap();
+    assert_eq!(scenarios.len(), 2);
+    assert_eq!(scenarios[0].map, "maps/test.map");
+    assert_eq!(scenarios[0].origin, (1, 1));
+    assert_eq!(scenarios[0].target, (4, 2));
+    assert_eq!(scenarios[1].bucket, 3);
+    assert_eq!(write_scenarios(&scenarios), text);
+}
+
+#[test]
+fn test_load_and_save_files() {
+    let directory = std::env::temp_dir();
+    let file = read_ascii("S.#\n..G\n").unwrap();
+    for extension in ["txt", "map"].iter() {
+        let path = directory.join(format!("tprime-test-{}.{}", std::process::id(), extension));
+        save(&path, &file).unwrap();
+        let loaded = load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.map.terrain()[(2, 0)], Terrain::Wall);
+        assert_eq!(loaded.map.len(), (3, 2));
+    }
+}
@@ -0,0 +1,132 @@
+//! The drawing protocol that frames are sent to the canvas in.
+
+use serde_derive::Serialize;
+use serdebug::SerDebug;
+
+/// A frame to draw, and how long to wait before asking for the next one.
+#[derive(Serialize, SerDebug, Default)]
+pub struct Output {
+    /// Milliseconds until the next frame, or 0 for the next animation frame.
+    pub timeout: u32,
+    pub width: u32,
+    pub height: u32,
+    /// The layers of the frame, from the bottom up.
+    pub layers: Vec<OutputLayer>,
+}
+
+impl Output {
+    /// An empty frame with the given layers, from the bottom up.
+    pub fn new(layers: &[&'static str]) -> Self {
+        Self {
+            layers: layers
+                .iter()
+                .map(|name| OutputLayer {
+                    name,
+                    ..Default::default()
+                }).collect(),
+            ..Default::default()
+        }
+    }
+
+    /// The layer with the given name, which is added on top if there isn't one yet.
+    pub fn layer(&mut self, name: &'static str) -> &mut OutputLayer {
+        match self.layers.iter().position(|layer| layer.name == name) {
+            Some(index) => &mut self.layers[index],
+            None => {
+                self.layers.push(OutputLayer {
+                    name,
+                    ..Default::default()
+                });
+                self.layers.last_mut().unwrap()
+            }
+        }
+    }
+}
+
+/// One layer of a frame. Within a layer, rects are drawn first, then polygons,
+/// lines, circles and text, so each kind of shape covers the ones before it.
+#[derive(Serialize, SerDebug, Default)]
+pub struct OutputLayer {
+    pub name: &'static str,
+    /// Whether the renderer keeps the layer from one frame to the next, so
+    /// only the shapes that have changed need to be drawn again. Each shape
+    /// should stay in the same place in the layer, with only its colour
+    /// changing, like the cells of a map.
+    pub retained: bool,
+    pub rects: Vec<OutputRect>,
+    pub polygons: Vec<OutputPolygon>,
+    pub lines: Vec<OutputLine>,
+    pub circles: Vec<OutputCircle>,
+    pub texts: Vec<OutputText>,
+}
+
+/// A filled axis-aligned rectangle.
+#[derive(Serialize, SerDebug)]
+pub struct OutputRect {
+    pub color: &'static str,
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+/// A filled polygon, for cells that aren't rectangles.
+#[derive(Serialize, SerDebug)]
+pub struct OutputPolygon {
+    pub color: &'static str,
+    pub points: Vec<(f64, f64)>,
+}
+
+/// A line stroked through a series of points, with round caps and joins.
+#[derive(Serialize, SerDebug)]
+pub struct OutputLine {
+    pub color: &'static str,
+    pub width: f64,
+    pub points: Vec<(f64, f64)>,
+}
+
+/// A filled circle.
+#[derive(Serialize, SerDebug)]
+pub struct OutputCircle {
+    pub color: &'static str,
+    pub x: f64,
+    pub y: f64,
+    pub radius: f64,
+}
+
+/// A text label, centred on its point.
+#[derive(Serialize, SerDebug)]
+pub struct OutputText {
+    pub color: &'static str,
+    pub x: f64,
+    pub y: f64,
+    /// The height of the font.
+    pub size: f64,
+    pub text: String,
+}
+
+/// Parses a CSS colour in `rgb(r, g, b)` or `rgba(r, g, b, a)` form into its
+/// red, green, blue and alpha, falling back to opaque black.
+pub fn parse_color(color: &str) -> [f32; 4] {
+    let parsed = color
+        .trim()
+        .trim_start_matches("rgba")
+        .trim_start_matches("rgb")
+        .trim_start_matches('(')
+        .trim_end_matches(')')
+        .split(',')
+        .map(|channel| channel.trim().parse::<f32>())
+        .collect::<Result<Vec<f32>, _>>();
+    match parsed.as_ref().map(|channels| &channels[..]) {
+        Ok([r, g, b]) => [*r, *g, *b, 1.],
+        Ok([r, g, b, a]) => [*r, *g, *b, *a],
+        _ => [0., 0., 0., 1.],
+    }
+}
+
+#[test]
+fn test_parse_color() {
+    assert_eq!(parse_color("rgba(192, 0, 64, 0.5)"), [192., 0., 64., 0.5]);
+    assert_eq!(parse_color("rgb(1,2,3)"), [1., 2., 3., 1.]);
+    assert_eq!(parse_color("tomato"), [0., 0., 0., 1.]);
+}
@@ -1,12 +1,28 @@
 use std::{
     cmp::Ordering,
     collections::hash_map::DefaultHasher,
-    collections::BinaryHeap,
+    collections::{BinaryHeap, HashSet},
+    fmt,
     hash::{Hash, Hasher},
     ops::{Index, IndexMut},
+    slice,
+    str::FromStr,
+    time::{Duration, Instant},
 };
 
 use log::{debug, error, info, log, trace, warn, Log};
+use serde_derive::{Deserialize, Serialize};
+
+#[cfg(test)]
+use rand::{prng::ChaChaRng, Rng, SeedableRng};
+
+use super::components::Components;
+use super::map::Map;
+#[cfg(test)]
+use super::terrain::{Terrain, TerrainCosts};
+use super::topology::Geometry;
+#[cfg(test)]
+use super::topology::DIAGONAL_STEP_COST;
 
 /// The value used for each axis of a Position.
 pub type Distance = usize;
@@ -14,6 +30,7 @@ pub type Distance = usize;
 pub type Position = (Distance, Distance);
 
 /// An fixed-size array of values indexed by an (x, y) tuple.
+#[derive(Clone, Serialize)]
 pub struct Array2D<Value>
 where
     Value: Clone + Default,
@@ -28,7 +45,7 @@ where
     Value: Clone + Default,
 {
     /// Constructs an Array2D with the given dimension filled with [Value::default].
-    fn new(width: Distance, height: Distance) -> Self {
+    pub fn new(width: Distance, height: Distance) -> Self {
         Self {
             width,
             height,
@@ -61,6 +78,45 @@ where
     }
 }
 
+/// Deserializing checks that there's a value for every cell, so a truncated
+/// or hand-edited grid is an error when it's loaded rather than a panic when
+/// it's first indexed.
+impl<'de, Value> serde::Deserialize<'de> for Array2D<Value>
+where
+    Value: Clone + Default + serde::Deserialize<'de>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct Unchecked<Value> {
+            width: Distance,
+            height: Distance,
+            values: Vec<Value>,
+        }
+
+        let Unchecked {
+            width,
+            height,
+            values,
+        } = serde::Deserialize::deserialize(deserializer)?;
+        if width.checked_mul(height) != Some(values.len()) {
+            return Err(serde::de::Error::custom(format!(
+                "a {}×{} grid can't have {} values",
+                width,
+                height,
+                values.len()
+            )));
+        }
+        Ok(Self {
+            width,
+            height,
+            values,
+        })
+    }
+}
+
 impl<Value> Index<Position> for Array2D<Value>
 where
     Value: Clone + Default,
@@ -82,35 +138,327 @@ where
     }
 }
 
-#[derive(Clone, Default, Copy)]
-pub struct AStarCell {
-    state: AStarCellState,
+/// The bookkeeping for a single cell during a search.
+#[derive(Clone, Default, Copy, Serialize, Deserialize)]
+struct SearchCell {
+    /// The generation of the search this was last written by. The rest of the
+    /// data is stale unless this matches the current generation.
+    generation: u32,
+    /// The cell this cell was reached from, or itself for the origin.
+    parent: Position,
+    /// The cost of the cheapest path found to this cell so far, its g-score.
+    cost: Distance,
+    /// Whether this cell has been expanded.
+    closed: bool,
+}
+
+/// The per-search state for each cell of a map, kept separate from the map so
+/// that it can be reset cheaply and reused for another search.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SearchState {
+    /// The current generation, incremented on each reset.
+    generation: u32,
+    /// The bookkeeping for each cell.
+    cells: Array2D<SearchCell>,
+    /// Paths that we're still exploring.
+    frontier: BinaryHeap<AStarPath>,
 }
 
-impl AStarCell {
-    pub fn state(&self) -> AStarCellState {
-        self.state
+impl SearchState {
+    /// Checks that a deserialized search is over a map of the given size, and
+    /// doesn't lead anywhere outside of it.
+    fn check(&self, size: Position) -> Result<(), String> {
+        let contains = |(x, y): Position| x < size.0 && y < size.1;
+        if self.cells.len() != size {
+            return Err(format!(
+                "a search over a {}×{} map can't be resumed on a {}×{} one",
+                self.cells.len().0,
+                self.cells.len().1,
+                size.0,
+                size.1
+            ));
+        }
+        let positions = self.cells.iter().map(|(_, cell)| cell.parent).chain(
+            self.frontier
+                .iter()
+                .flat_map(|path| Some(path.head).into_iter().chain(path.parent)),
+        );
+        for position in positions {
+            if !contains(position) {
+                return Err(format!("the search reaches {:?}, off the map", position));
+            }
+        }
+        Ok(())
+    }
+
+    /// Constructs an empty search state for a map of the given size.
+    pub fn new(size: Position) -> Self {
+        Self {
+            generation: 1,
+            cells: Array2D::new(size.0, size.1),
+            frontier: BinaryHeap::new(),
+        }
+    }
+
+    /// Forgets everything about the previous search, without touching each cell.
+    pub fn reset(&mut self) {
+        self.frontier.clear();
+        if self.generation == u32::max_value() {
+            let (width, height) = self.cells.len();
+            self.cells = Array2D::new(width, height);
+            self.generation = 1;
+        } else {
+            self.generation += 1;
+        }
+    }
+
+    fn cell(&self, position: Position) -> Option<&SearchCell> {
+        let cell = &self.cells[position];
+        if cell.generation == self.generation {
+            Some(cell)
+        } else {
+            None
+        }
+    }
+
+    /// The cell that a closed cell was reached from, or itself for the origin.
+    pub fn parent(&self, position: Position) -> Option<Position> {
+        self.cell(position)
+            .filter(|cell| cell.closed)
+            .map(|cell| cell.parent)
+    }
+
+    /// The cost of the cheapest path found to a closed cell.
+    pub fn cost(&self, position: Position) -> Option<Distance> {
+        self.cell(position)
+            .filter(|cell| cell.closed)
+            .map(|cell| cell.cost)
+    }
+
+    /// The cells at the heads of the paths in the frontier, which may include
+    /// cells that have since been closed.
+    pub fn frontier(&self) -> impl Iterator<Item = Position> + '_ {
+        self.frontier.iter().map(|path| path.head)
+    }
+
+    /// Whether a cell has been expanded by this search.
+    pub fn closed(&self, position: Position) -> bool {
+        self.cell(position).map_or(false, |cell| cell.closed)
+    }
+
+    /// The cost of the cheapest path found to a cell so far, whether or not
+    /// it's been expanded yet.
+    pub fn best_cost(&self, position: Position) -> Option<Distance> {
+        self.cell(position).map(|cell| cell.cost)
+    }
+
+    /// The cell that the cheapest path found to a cell so far came from,
+    /// whether or not it's been expanded yet.
+    fn best_parent(&self, position: Position) -> Option<Position> {
+        self.cell(position).map(|cell| cell.parent)
+    }
+
+    /// Records a path to an open cell if it's cheaper than any found so far,
+    /// returning whether it was. Paths that don't improve on the g-score aren't
+    /// worth adding to the frontier.
+    pub fn relax(&mut self, position: Position, parent: Position, cost: Distance) -> bool {
+        match self.cell(position) {
+            Some(cell) if cell.closed || cell.cost <= cost => false,
+            _ => {
+                self.cells[position] = SearchCell {
+                    generation: self.generation,
+                    parent,
+                    cost,
+                    closed: false,
+                };
+                true
+            }
+        }
+    }
+
+    /// Marks a cell as expanded, having been reached from `parent` at `cost`.
+    pub fn close(&mut self, position: Position, parent: Position, cost: Distance) {
+        self.cells[position] = SearchCell {
+            generation: self.generation,
+            parent,
+            cost,
+            closed: true,
+        };
     }
 }
 
-#[derive(Clone, PartialEq, Copy)]
-pub enum AStarCellState {
-    Free,
-    Blocked,
-    VisitedFrom(Position),
+/// The order in which the frontier of potential paths is explored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum SearchStrategy {
+    /// Prioritize by cost so far plus the heuristic's estimate of the remaining cost.
+    AStar,
+    /// Prioritize by cost so far, ignoring the target.
+    Dijkstra,
+    /// Prioritize by number of steps taken so far, ignoring costs and the target.
+    BreadthFirst,
+    /// Prioritize the paths that have taken the most steps so far.
+    DepthFirst,
+    /// Prioritize by the heuristic's estimate of the remaining cost alone.
+    Greedy,
 }
 
-impl Default for AStarCellState {
+impl SearchStrategy {
+    /// Every strategy, in the order the demo cycles through them.
+    pub const ALL: [SearchStrategy; 5] = [
+        SearchStrategy::AStar,
+        SearchStrategy::Dijkstra,
+        SearchStrategy::BreadthFirst,
+        SearchStrategy::DepthFirst,
+        SearchStrategy::Greedy,
+    ];
+
+    /// Whether this strategy's priorities are lower bounds on the cost of any
+    /// path to the target through the paths they're given to, so that once
+    /// they reach the cost of a known path, no cheaper one remains.
+    fn bounds_cost(self) -> bool {
+        self == SearchStrategy::AStar || self == SearchStrategy::Dijkstra
+    }
+
+    /// The priority of a path under this strategy; lower values are explored first.
+    fn priority(
+        self,
+        cost_from_origin: Distance,
+        steps: Distance,
+        min_cost_to_target: Distance,
+    ) -> Distance {
+        use self::SearchStrategy::*;
+        match self {
+            AStar => cost_from_origin + min_cost_to_target,
+            Dijkstra => cost_from_origin,
+            BreadthFirst => steps,
+            DepthFirst => Distance::max_value() / 2 - steps,
+            Greedy => min_cost_to_target,
+        }
+    }
+}
+
+impl FromStr for SearchStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        use self::SearchStrategy::*;
+        match s {
+            "astar" => Ok(AStar),
+            "dijkstra" => Ok(Dijkstra),
+            "bfs" => Ok(BreadthFirst),
+            "dfs" => Ok(DepthFirst),
+            "greedy" => Ok(Greedy),
+            _ => Err(format!(
+                "unknown algorithm {:?}, expected astar, dijkstra, bfs, dfs or greedy",
+                s
+            )),
+        }
+    }
+}
+
+impl Default for SearchStrategy {
     fn default() -> Self {
-        AStarCellState::Free
+        SearchStrategy::AStar
+    }
+}
+
+impl fmt::Display for SearchStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use self::SearchStrategy::*;
+        write!(
+            f,
+            "{}",
+            match self {
+                AStar => "A*",
+                Dijkstra => "Dijkstra's",
+                BreadthFirst => "breadth-first search",
+                DepthFirst => "depth-first search",
+                Greedy => "greedy best-first search",
+            }
+        )
+    }
+}
+
+/// How a search ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum PathOutcome {
+    /// A path to the target was found.
+    Found,
+    /// Every reachable cell was explored without finding the target.
+    Unreachable,
+    /// The search was stopped before it finished.
+    Aborted,
+    /// The search expanded as many cells as its budget allowed without finding the target.
+    BudgetExceeded,
+}
+
+impl fmt::Display for PathOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use self::PathOutcome::*;
+        write!(
+            f,
+            "{}",
+            match self {
+                Found => "found",
+                Unreachable => "unreachable",
+                Aborted => "aborted",
+                BudgetExceeded => "budget exceeded",
+            }
+        )
+    }
+}
+
+/// How far a search has got.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum SearchProgress {
+    /// The search has more work to do.
+    InProgress,
+    /// The search has finished, in the given way.
+    Finished(PathOutcome),
+}
+
+/// The result of a search, along with some statistics about how it went.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PathResult {
+    /// How the search ended, or `None` if it hasn't yet.
+    pub outcome: Option<PathOutcome>,
+    /// The path from the origin to the target if one was found, otherwise
+    /// the most promising partial path, or nothing if there are none left.
+    pub path: Vec<Position>,
+    /// The total cost of `path`.
+    pub cost: Distance,
+    /// The number of cells that were expanded.
+    pub nodes_expanded: usize,
+    /// The largest number of paths that were in the frontier at once.
+    pub frontier_peak: usize,
+}
+
+impl PathResult {
+    pub fn found(&self) -> bool {
+        self.outcome == Some(PathOutcome::Found)
+    }
+
+    /// The path, if one to the target was found.
+    pub fn into_path(self) -> Option<Vec<Position>> {
+        if self.found() {
+            Some(self.path)
+        } else {
+            None
+        }
     }
 }
 
 /// A potential path.
-#[derive(Hash, Eq, PartialEq)]
+#[derive(Clone, Hash, Eq, PartialEq, Serialize, Deserialize)]
 pub struct AStarPath {
     /// The position at the end of this path.
     head: Position,
+    /// The position this path reached its head from, if it's not just the origin.
+    parent: Option<Position>,
+    /// The priority of this path according to the pathfinder's strategy.
+    priority: Distance,
+    /// The number of steps this path has taken so far.
+    steps: Distance,
     /// The cost that this path has taken so far.
     cost_from_origin: Distance,
     /// Our heuristic's lower bound on the cost to the target.
@@ -120,18 +468,6 @@ pub struct AStarPath {
 }
 
 impl AStarPath {
-    fn min_cost(&self) -> Distance {
-        // Exclude cost_from_origin and you get dijkstra’s (prioritize options that seem closer to target).
-        // Exclude min_cost_to_target and you get breadth-first-search (prioritize options close to source).
-        // Include both and we have A*.
-        let a_star = self.cost_from_origin + self.min_cost_to_target;
-        let dijkstras = self.min_cost_to_target;
-        let bfs = self.cost_from_origin;
-        let dfs = Distance::max_value() / 2 - self.cost_from_origin;
-
-        a_star
-    }
-
     fn default_hash(&self) -> u64 {
         let mut hasher = DefaultHasher::default();
         self.hash(&mut hasher);
@@ -142,7 +478,7 @@ impl AStarPath {
 /// The priority of exploring this path relative to other potential paths.
 impl Ord for AStarPath {
     fn cmp(&self, other: &Self) -> Ordering {
-        let cost_ordering = self.min_cost().cmp(&other.min_cost()).reverse();
+        let priority_ordering = self.priority.cmp(&other.priority).reverse();
 
         let min_cost_to_target_ordering = self
             .min_cost_to_target
@@ -156,7 +492,7 @@ impl Ord for AStarPath {
 
         let arbitrary_stable_ordering = self.default_hash().cmp(&other.default_hash());
 
-        cost_ordering
+        priority_ordering
             .then(min_cost_to_target_ordering)
             .then(min_diagonless_distance_to_target_ordering)
             .then(arbitrary_stable_ordering)
@@ -169,200 +505,1222 @@ impl PartialOrd for AStarPath {
     }
 }
 
+/// A search over a map, which can be paused, serialized and resumed at any step.
+///
+/// The derived (de)serialization is generated as inherent functions, so that
+/// deserializing can check the parts of the search fit together before it's
+/// resumed.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(remote = "Self")]
 pub struct AStarPathfinder {
-    /// Whether this is still running.
-    working: bool,
-    /// The width of the grid in cells.
-    width: Distance,
-    /// The height of the grid in cells.
-    height: Distance,
+    /// How the search ended, or `None` if it's still running.
+    outcome: Option<PathOutcome>,
+    /// The most cells a search may expand before giving up, if limited.
+    budget: Option<usize>,
+    /// The number of cells expanded by the current search.
+    nodes_expanded: usize,
+    /// The largest the frontier has been during the current search.
+    frontier_peak: usize,
+    /// The grid we're searching.
+    map: Map,
     /// The point at which our paths start.
     origin: Position,
-    /// The point our paths are trying to find.
-    target: Position,
+    /// The points our paths are trying to find, any one of which will do.
+    targets: Vec<Position>,
+    /// The order in which we explore potential paths.
+    strategy: SearchStrategy,
     /// Pathfinding data associated with each cell of the grid.
-    data: Array2D<AStarCell>,
-    /// Paths that we're still exploring.
-    frontier: BinaryHeap<AStarPath>,
+    search: SearchState,
+    /// Pathfinding data for a second search from the target back toward the
+    /// origin, if we're searching from both ends.
+    backward_search: Option<SearchState>,
+    /// The cell where the cheapest path found so far between the two searches
+    /// meets, along with that path's cost.
+    meeting: Option<(Position, Distance)>,
+    /// The connected components of the map, if they've been labelled, which
+    /// let searches between cells that can't reach each other end at once.
+    components: Option<Components>,
+}
+
+impl serde::Serialize for AStarPathfinder {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        Self::serialize(self, serializer)
+    }
+}
+
+/// Deserializing rejects a search whose state doesn't match its map, such as
+/// one from a bug report that's been cut short or edited by hand.
+impl<'de> serde::Deserialize<'de> for AStarPathfinder {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        let pathfinder = Self::deserialize(deserializer)?;
+        pathfinder.check().map_err(serde::de::Error::custom)?;
+        Ok(pathfinder)
+    }
 }
 
 impl AStarPathfinder {
+    /// Checks that each part of a deserialized search is over the same map.
+    fn check(&self) -> Result<(), String> {
+        let size = self.map.len();
+        self.search.check(size)?;
+        if let Some(search) = &self.backward_search {
+            search.check(size)?;
+        }
+        if let Some(components) = &self.components {
+            if components.labels().len() != size {
+                return Err("the components are for a different size of map".to_string());
+            }
+        }
+        let ends = Some(self.origin)
+            .into_iter()
+            .chain(self.targets.iter().cloned())
+            .chain(self.meeting.map(|(position, _)| position));
+        for position in ends {
+            if !self.map.contains(position) {
+                return Err(format!("{:?} is off the map", position));
+            }
+        }
+        Ok(())
+    }
+
+    /// Constructs a pathfinder over a map, searching with the given strategy.
+    pub fn new(map: Map, origin: Position, target: Position, strategy: SearchStrategy) -> Self {
+        let search = SearchState::new(map.len());
+        let mut self_ = Self {
+            map,
+            origin,
+            targets: vec![target],
+            strategy,
+            search,
+            backward_search: None,
+            meeting: None,
+            components: None,
+            outcome: None,
+            budget: None,
+            nodes_expanded: 0,
+            frontier_peak: 0,
+        };
+        self_.reset(origin, target);
+        self_
+    }
+
+    /// Discards the current search and starts a new one over the same map.
+    pub fn reset(&mut self, origin: Position, target: Position) {
+        self.reset_many(origin, &[target]);
+    }
+
+    /// Discards the current search and starts a new one over the same map,
+    /// which finishes at whichever of `targets` is the cheapest to reach.
+    pub fn reset_many(&mut self, origin: Position, targets: &[Position]) {
+        self.origin = origin;
+        self.targets = targets.to_vec();
+        self.outcome = None;
+        self.nodes_expanded = 0;
+        self.search.reset();
+        self.search.relax(origin, origin, 0);
+        let path = self.initial_path(origin, targets);
+        self.search.frontier.push(path);
+        self.frontier_peak = 1;
+
+        self.meeting = None;
+        if self.backward_search.is_some() {
+            let paths: Vec<AStarPath> = targets
+                .iter()
+                .map(|target| self.initial_path(*target, &[origin]))
+                .collect();
+            let backward = self.backward_search.as_mut().unwrap();
+            backward.reset();
+            for target in targets {
+                backward.relax(*target, *target, 0);
+            }
+            backward.frontier.extend(paths);
+            self.frontier_peak += backward.frontier.len();
+            if targets.contains(&origin) {
+                self.meeting = Some((origin, 0));
+            }
+        }
+
+        if !targets.is_empty() && !targets.iter().any(|target| self.reachable(*target)) {
+            debug!("No target is connected to the origin.");
+            self.outcome = Some(PathOutcome::Unreachable);
+        }
+    }
+
+    /// Whether a target can be reached from the origin, as far as we know
+    /// without searching. An origin on impassable terrain isn't in any
+    /// component, but can still be stepped out of, so only a search can tell.
+    fn reachable(&self, target: Position) -> bool {
+        match &self.components {
+            Some(components) if components.label(self.origin).is_some() => {
+                target == self.origin || components.connected(self.origin, target)
+            }
+            _ => true,
+        }
+    }
+
+    /// The path that a search from `start` toward the nearest of `goals` begins with.
+    fn initial_path(&self, start: Position, goals: &[Position]) -> AStarPath {
+        let (min_cost_to_target, diagonless_distance_to_target) =
+            Self::estimate(&self.map, start, goals);
+        AStarPath {
+            head: start,
+            parent: None,
+            priority: self.strategy.priority(0, 0, min_cost_to_target),
+            steps: 0,
+            cost_from_origin: 0,
+            min_cost_to_target,
+            diagonless_distance_to_target,
+        }
+    }
+
+    /// Changes the strategy and starts a new search over the same map.
+    pub fn set_strategy(&mut self, strategy: SearchStrategy) {
+        self.strategy = strategy;
+        self.restart();
+    }
+
+    /// Starts a new search between the same points.
+    fn restart(&mut self) {
+        let (origin, targets) = (self.origin, self.targets.clone());
+        self.reset_many(origin, &targets);
+    }
+
+    /// Switches between searching from the origin alone and searching from
+    /// both ends until the two searches meet, and starts a new search.
+    ///
+    /// Searching from both ends finds that a target is unreachable much sooner
+    /// when either end is shut in a small pocket, since that end's search runs
+    /// out of cells quickly.
+    pub fn set_bidirectional(&mut self, bidirectional: bool) {
+        self.backward_search = if bidirectional {
+            Some(SearchState::new(self.map.len()))
+        } else {
+            None
+        };
+        self.restart();
+    }
+
+    pub fn bidirectional(&self) -> bool {
+        self.backward_search.is_some()
+    }
+
+    /// Sets the connected components of the map, which must be labelled from
+    /// the map as it is now, and starts a new search. While they're set, a
+    /// search whose origin isn't connected to any of its targets is found to
+    /// be unreachable as soon as it starts, without expanding anything.
+    pub fn set_components(&mut self, components: Option<Components>) {
+        self.components = components;
+        self.restart();
+    }
+
+    pub fn components(&self) -> Option<&Components> {
+        self.components.as_ref()
+    }
+
+    /// Limits the number of cells a search may expand before it gives up, taking
+    /// effect from the next step.
+    pub fn set_budget(&mut self, budget: Option<usize>) {
+        self.budget = budget;
+    }
+
+    /// Resets the search and runs it to completion.
+    pub fn find_path(&mut self, origin: Position, target: Position) -> PathResult {
+        self.reset(origin, target);
+        self.get_path()
+    }
+
+    /// Resets the search and runs it to completion, finding a path to the
+    /// cheapest of `targets` to reach. The path ends at the one that was chosen.
+    pub fn find_nearest(&mut self, origin: Position, targets: &[Position]) -> PathResult {
+        self.reset_many(origin, targets);
+        self.get_path()
+    }
+
+    /// Finds the cost of the cheapest path from `origin` to each of `targets`
+    /// with a single Dijkstra's search, which runs until every target has been
+    /// reached or there's nowhere left to go. Targets that can't be reached,
+    /// or that the budget runs out before reaching, have no cost.
+    ///
+    /// This ignores the strategy and the bidirectional setting, and leaves the
+    /// finished search in place so that it can be inspected with [Self::search].
+    pub fn costs_to(&mut self, origin: Position, targets: &[Position]) -> Vec<Option<Distance>> {
+        let strategy = self.strategy;
+        let backward_search = self.backward_search.take();
+        self.strategy = SearchStrategy::Dijkstra;
+
+        // With no targets of its own, the search floods outward until it's
+        // stopped, so we stop it once the last of our targets is expanded.
+        self.reset_many(origin, &[]);
+        let mut remaining: HashSet<Position> = targets
+            .iter()
+            .cloned()
+            .filter(|target| self.reachable(*target))
+            .collect();
+        while !remaining.is_empty() && self.working() {
+            let head = self.search.frontier.peek().map(|path| path.head);
+            self.step();
+            if let Some(head) = head {
+                remaining.remove(&head);
+            }
+        }
+        if self.working() {
+            self.outcome = Some(PathOutcome::Found);
+        }
+
+        self.strategy = strategy;
+        self.backward_search = backward_search;
+        targets
+            .iter()
+            .map(|target| self.search.cost(*target))
+            .collect()
+    }
+
+    pub fn map(&self) -> &Map {
+        &self.map
+    }
+
+    /// Mutable access to the map. Changes won't be reflected in a search that's
+    /// already in progress, so this should be followed by a [Self::reset], and
+    /// by [Self::set_components] if the components were set.
+    pub fn map_mut(&mut self) -> &mut Map {
+        &mut self.map
+    }
+
+    pub fn into_map(self) -> Map {
+        self.map
+    }
+
+    pub fn search(&self) -> &SearchState {
+        &self.search
+    }
+
+    /// The search from the target back toward the origin, if we're searching
+    /// from both ends. Each cell's parent is the next step toward the target.
+    pub fn backward_search(&self) -> Option<&SearchState> {
+        self.backward_search.as_ref()
+    }
+
+    pub fn origin(&self) -> Position {
+        self.origin
+    }
+
+    pub fn targets(&self) -> &[Position] {
+        &self.targets
+    }
+
+    pub fn strategy(&self) -> SearchStrategy {
+        self.strategy
+    }
+
     pub fn working(&self) -> bool {
-        self.working
+        self.outcome.is_none()
+    }
+
+    /// How far the search has got.
+    pub fn progress(&self) -> SearchProgress {
+        match self.outcome {
+            Some(outcome) => SearchProgress::Finished(outcome),
+            None => SearchProgress::InProgress,
+        }
+    }
+
+    /// Advances the search by up to `steps` steps, stopping early if it finishes.
+    pub fn step_n(&mut self, steps: usize) -> SearchProgress {
+        for _ in 0..steps {
+            if !self.working() {
+                break;
+            }
+            self.step();
+        }
+        self.progress()
+    }
+
+    /// Advances the search until it finishes or roughly `duration` has passed.
+    ///
+    /// This relies on [Instant], which isn't available on the web, where
+    /// [Self::step_n] should be used instead.
+    pub fn step_for(&mut self, duration: Duration) -> SearchProgress {
+        let start = Instant::now();
+        while self.working() && start.elapsed() < duration {
+            self.step_n(64);
+        }
+        self.progress()
     }
 
-    pub fn data(&self) -> &Array2D<AStarCell> {
-        &self.data
+    /// Stops the search where it is.
+    pub fn abort(&mut self) {
+        if self.working() {
+            self.outcome = Some(PathOutcome::Aborted);
+        }
     }
 
-    pub fn get_path(&mut self) -> Option<Vec<Position>> {
-        while self.working {
+    /// Runs the search to completion.
+    pub fn get_path(&mut self) -> PathResult {
+        while self.working() {
             self.step();
         }
 
         self.peek_path()
     }
 
-    pub fn peek_path(&mut self) -> Option<Vec<Position>> {
-        if let Some(path) = self.frontier.peek() {
-            let mut full_path = Vec::new();
-            let mut current = path.head;
+    /// The result of the search so far, which has no outcome and the most
+    /// promising partial path if it's still running.
+    pub fn peek_path(&self) -> PathResult {
+        let (path, cost) = self.best_path();
+        PathResult {
+            outcome: self.outcome,
+            path,
+            cost,
+            nodes_expanded: self.nodes_expanded,
+            frontier_peak: self.frontier_peak,
+        }
+    }
+
+    /// The positions and cost of the path at the front of the frontier, or of
+    /// the cheapest path found between the two searches once they've met.
+    fn best_path(&self) -> (Vec<Position>, Distance) {
+        if let (Some(backward), Some((meeting, cost))) = (&self.backward_search, self.meeting) {
+            let mut full_path = vec![meeting];
+            let mut current = meeting;
+            while current != self.origin {
+                current = self.search.best_parent(current).unwrap();
+                full_path.push(current);
+            }
+            full_path.reverse();
 
+            // Each target is its own parent in the backward search.
+            let mut current = meeting;
             loop {
+                let next = backward.best_parent(current).unwrap();
+                if next == current {
+                    break;
+                }
+                current = next;
                 full_path.push(current);
-                if let AStarCellState::VisitedFrom(neighbour) = self.data[current].state {
-                    current = neighbour;
-                } else {
+            }
+
+            (full_path, cost)
+        } else if let Some(path) = self.search.frontier.peek() {
+            let mut full_path = vec![path.head];
+            let mut current = path.parent;
+
+            while let Some(position) = current {
+                full_path.push(position);
+                if position == self.origin {
                     break;
                 }
+                current = self.search.parent(position);
             }
 
             full_path.reverse();
 
-            Some(full_path)
+            (full_path, path.cost_from_origin)
         } else {
-            None
+            (Vec::new(), 0)
         }
     }
 
     /// Advances the pathfinding by one step.
+    ///
+    /// Cells are only marked as visited once they're expanded, not when they're
+    /// first added to the frontier, so a cheaper path to a cell that's found
+    /// before it's expanded still takes precedence. Paths that are no cheaper
+    /// than the best known path to their cell are never added to the frontier,
+    /// and any that are superseded once they're in it are lazily deleted: they're
+    /// skipped when they're popped, because their cell has already been closed.
     pub fn step(&mut self) {
-        if !self.working {
+        if !self.working() {
+            return;
+        }
+
+        if self
+            .budget
+            .map_or(false, |budget| self.nodes_expanded >= budget)
+        {
+            debug!("Expansion budget exhausted without finding end point.");
+            self.outcome = Some(PathOutcome::BudgetExceeded);
             return;
         }
 
-        if let Some(path) = { self.frontier.pop() } {
-            if path.head == self.target {
+        if self.backward_search.is_some() {
+            self.step_bidirectional();
+            return;
+        }
+
+        if let Some(path) = { self.search.frontier.pop() } {
+            if self.search.closed(path.head) {
+                trace!("Skipping already-visited {:?}.", path.head);
+                return;
+            }
+
+            self.search.close(
+                path.head,
+                path.parent.unwrap_or(path.head),
+                path.cost_from_origin,
+            );
+            self.nodes_expanded += 1;
+
+            if self.targets.contains(&path.head) {
                 debug!("Found path to target.");
-                self.working = false;
+                self.outcome = Some(PathOutcome::Found);
                 // Put it back in front.
-                self.frontier.push(path);
+                self.search.frontier.push(path);
             } else {
-                let open_neighbours: Vec<Position> = self
-                    .neighbours(path.head)
-                    .into_iter()
-                    .filter(|position| self.data[*position].state == AStarCellState::Free)
-                    .collect();
-
-                debug!(
-                    "Exploring {:?} new neighbours of {:?}.",
-                    open_neighbours.len(),
-                    path.head
+                Self::expand(
+                    &self.map,
+                    &mut self.search,
+                    self.strategy,
+                    &self.targets,
+                    false,
+                    &path,
                 );
-
-                for neighbour in open_neighbours.iter() {
-                    self.data[*neighbour].state = AStarCellState::VisitedFrom(path.head);
-                }
-
-                let new_frontier: Vec<AStarPath> = open_neighbours
-                    .into_iter()
-                    .map(|position| AStarPath {
-                        head: position,
-                        cost_from_origin: path.cost_from_origin + 1,
-                        min_cost_to_target: Self::min_distance(position, self.target),
-                        diagonless_distance_to_target: Self::diagonless_distance(
-                            position,
-                            self.target,
-                        ),
-                    }).collect();
-
-                self.frontier.extend(new_frontier);
+                self.frontier_peak = self.frontier_peak.max(self.search.frontier.len());
             }
         } else {
             debug!("Frontier exhausted without finding end point.");
-            self.working = false;
+            self.outcome = Some(PathOutcome::Unreachable);
         }
     }
 
-    fn neighbours(&self, position: Position) -> Vec<Position> {
-        let (x, y) = position;
-        let mut vec = Vec::with_capacity(8);
-        if x > 0 {
-            if y > 0 {
-                vec.push((x - 1, y - 1))
-            }
-            vec.push((x - 1, y));
-            if y + 1 < self.height {
-                vec.push((x - 1, y + 1))
+    /// Advances a search from both ends by expanding a cell from whichever
+    /// side has the smaller frontier.
+    ///
+    /// The searches have met once a cell has been reached by both, but the
+    /// first meeting isn't necessarily on the cheapest path. For strategies
+    /// whose priorities bound the cost of a path, we keep going until one side's
+    /// best priority reaches the cost of the cheapest meeting so far; for the
+    /// rest, the first meeting is as good as any.
+    fn step_bidirectional(&mut self) {
+        let strategy = self.strategy;
+        let forward = &mut self.search;
+        let backward = self.backward_search.as_mut().unwrap();
+
+        let exhausted = forward.frontier.is_empty() || backward.frontier.is_empty();
+        if let Some((_, cost)) = self.meeting {
+            let settled = |search: &SearchState| {
+                search
+                    .frontier
+                    .peek()
+                    .map_or(true, |path| path.priority >= cost)
+            };
+            if exhausted || !strategy.bounds_cost() || settled(forward) || settled(backward) {
+                debug!("Found path to target where the searches met.");
+                self.outcome = Some(PathOutcome::Found);
+                return;
             }
+        } else if exhausted {
+            debug!("Frontier exhausted without the searches meeting.");
+            self.outcome = Some(PathOutcome::Unreachable);
+            return;
         }
-        if y > 0 {
-            vec.push((x, y - 1))
-        }
-        vec.push((x, y));
-        if y + 1 < self.height {
-            vec.push((x, y + 1))
+
+        let (search, other, goals, reverse) = if forward.frontier.len() <= backward.frontier.len() {
+            (forward, backward, &self.targets[..], false)
+        } else {
+            (backward, forward, slice::from_ref(&self.origin), true)
+        };
+
+        let path = search.frontier.pop().unwrap();
+        if search.closed(path.head) {
+            trace!("Skipping already-visited {:?}.", path.head);
+            return;
         }
-        if x + 1 < self.width {
-            if y > 0 {
-                vec.push((x + 1, y - 1))
-            }
-            vec.push((x + 1, y));
-            if y + 1 < self.height {
-                vec.push((x + 1, y + 1))
+        search.close(
+            path.head,
+            path.parent.unwrap_or(path.head),
+            path.cost_from_origin,
+        );
+        self.nodes_expanded += 1;
+
+        for (position, cost) in Self::expand(&self.map, search, strategy, goals, reverse, &path) {
+            if let Some(other_cost) = other.best_cost(position) {
+                let cost = cost + other_cost;
+                if self.meeting.map_or(true, |(_, best)| cost < best) {
+                    trace!("Searches met at {:?} with cost {}.", position, cost);
+                    self.meeting = Some((position, cost));
+                }
             }
         }
-        vec
+
+        let frontier_len = search.frontier.len() + other.frontier.len();
+        self.frontier_peak = self.frontier_peak.max(frontier_len);
     }
 
-    /// Minimum distnace between two points with 8-way movement allowed.
-    fn min_distance(a: Position, b: Position) -> Distance {
-        (a.0.max(b.0) - a.0.min(b.0)).max(a.1.max(b.1) - a.1.min(b.1))
+    /// Adds a path to each open neighbour of an expanded path's head to the
+    /// frontier, if it's the cheapest found to that neighbour so far, and
+    /// returns those neighbours with the costs of their new paths.
+    ///
+    /// A `reverse` search runs from the targets back toward the origin, so it
+    /// steps from each neighbour into the head instead.
+    fn expand(
+        map: &Map,
+        search: &mut SearchState,
+        strategy: SearchStrategy,
+        goals: &[Position],
+        reverse: bool,
+        path: &AStarPath,
+    ) -> Vec<(Position, Distance)> {
+        let mut open_neighbours: Vec<(Position, Distance)> = map
+            .neighbours(path.head)
+            .into_iter()
+            .filter(|position| !search.closed(*position))
+            .filter_map(|position| {
+                let step_cost = if reverse {
+                    map.step_cost(position, path.head)
+                } else {
+                    map.step_cost(path.head, position)
+                };
+                step_cost.map(|cost| (position, path.cost_from_origin + cost))
+            }).collect();
+        open_neighbours.retain(|(position, cost)| search.relax(*position, path.head, *cost));
+
+        debug!(
+            "Exploring {:?} new neighbours of {:?}.",
+            open_neighbours.len(),
+            path.head
+        );
+
+        search
+            .frontier
+            .extend(open_neighbours.iter().map(|(position, cost_from_origin)| {
+                let steps = path.steps + 1;
+                let (min_cost_to_target, diagonless_distance_to_target) =
+                    Self::estimate(map, *position, goals);
+                AStarPath {
+                    head: *position,
+                    parent: Some(path.head),
+                    priority: strategy.priority(*cost_from_origin, steps, min_cost_to_target),
+                    steps,
+                    cost_from_origin: *cost_from_origin,
+                    min_cost_to_target,
+                    diagonless_distance_to_target,
+                }
+            }));
+
+        open_neighbours
+    }
+
+    /// The heuristic's lower bound on the cost from a cell to the nearest of
+    /// `goals`, and the least diagonless distance to any of them. The nearest
+    /// goal's bound is as consistent as each goal's own, so searches that rely
+    /// on that still find the cheapest path.
+    fn estimate(map: &Map, position: Position, goals: &[Position]) -> (Distance, Distance) {
+        let min_cost_to_target = goals
+            .iter()
+            .map(|goal| map.heuristic(position, *goal))
+            .min()
+            .unwrap_or(0);
+        let diagonless_distance_to_target = goals
+            .iter()
+            .map(|goal| Self::diagonless_distance(position, *goal))
+            .min()
+            .unwrap_or(0);
+        (min_cost_to_target, diagonless_distance_to_target)
     }
 
+    /// Distance between two points along the axes, used to break ties in favour of straighter paths.
     fn diagonless_distance(a: Position, b: Position) -> Distance {
         (a.0.max(b.0) - a.0.min(b.0)) + (a.1.max(b.1) - a.1.min(b.1))
     }
 
-    pub fn demo(demo_iteration: usize) -> Self {
-        let mut self_ = Self::default();
+    pub fn demo(demo_iteration: usize, geometry: Geometry, strategy: SearchStrategy) -> Self {
+        Self::new(
+            Map::demo(demo_iteration, geometry),
+            (1, 1),
+            (62, 62),
+            strategy,
+        )
+    }
+}
 
-        let ref mut array = &mut self_.data;
-        array[self_.origin].state = AStarCellState::Blocked;
+impl Default for AStarPathfinder {
+    fn default() -> Self {
+        let width = 64;
+        let height = 64;
+        Self::new(
+            Map::new(width, height, Geometry::default()),
+            (1, 1),
+            (width - 2, height - 2),
+            SearchStrategy::default(),
+        )
+    }
+}
 
-        for x in 0..self_.width {
-            for y in 0..self_.height {
-                if x + y < 5 {
-                    continue;
+/// Builds a pathfinder over a small grid with a wall that has to be walked around.
+#[cfg(test)]
+fn walled_pathfinder(geometry: Geometry, strategy: SearchStrategy) -> AStarPathfinder {
+    let mut map = Map::new(12, 10, geometry);
+    for y in 0..9 {
+        map.set_terrain((5, y), Terrain::Wall);
+    }
+    AStarPathfinder::new(map, (1, 2), (10, 2), strategy)
+}
+
+/// Asserts that a path runs from origin to target through passable, adjacent cells.
+#[cfg(test)]
+fn assert_valid_path(pathfinder: &AStarPathfinder, path: &[Position]) {
+    assert_eq!(path.first(), Some(&pathfinder.origin));
+    assert!(pathfinder.targets.contains(path.last().unwrap()));
+    for pair in path.windows(2) {
+        assert!(pathfinder.map.neighbours(pair[0]).contains(&pair[1]));
+        assert!(pathfinder.map.step_cost(pair[0], pair[1]).is_some());
+    }
+}
+
+/// Counts the steps on a shortest path from origin to target with a plain breadth-first flood fill.
+#[cfg(test)]
+fn flood_fill_steps(pathfinder: &AStarPathfinder) -> Option<Distance> {
+    let mut steps =
+        Array2D::<Option<Distance>>::new(pathfinder.map.width(), pathfinder.map.height());
+    let mut queue = std::collections::VecDeque::new();
+    steps[pathfinder.origin] = Some(0);
+    queue.push_back(pathfinder.origin);
+    while let Some(position) = queue.pop_front() {
+        let next = steps[position].unwrap() + 1;
+        for neighbour in pathfinder.map.neighbours(position) {
+            if steps[neighbour].is_none() && pathfinder.map.step_cost(position, neighbour).is_some()
+            {
+                steps[neighbour] = Some(next);
+                queue.push_back(neighbour);
+            }
+        }
+    }
+    steps[pathfinder.targets[0]]
+}
+
+/// Finds the cost of a cheapest path from origin to target with a plain Dijkstra's search.
+#[cfg(test)]
+fn reference_cost(pathfinder: &AStarPathfinder) -> Option<Distance> {
+    reference_costs(pathfinder)[pathfinder.targets[0]]
+}
+
+/// Finds the cost of a cheapest path from origin to every cell with a plain Dijkstra's search.
+#[cfg(test)]
+fn reference_costs(pathfinder: &AStarPathfinder) -> Array2D<Option<Distance>> {
+    use std::cmp::Reverse;
+    let mut costs =
+        Array2D::<Option<Distance>>::new(pathfinder.map.width(), pathfinder.map.height());
+    let mut queue = BinaryHeap::new();
+    costs[pathfinder.origin] = Some(0);
+    queue.push(Reverse((0, pathfinder.origin)));
+    while let Some(Reverse((cost, position))) = queue.pop() {
+        if costs[position] != Some(cost) {
+            continue;
+        }
+        for neighbour in pathfinder.map.neighbours(position) {
+            if let Some(step_cost) = pathfinder.map.step_cost(position, neighbour) {
+                let next = cost + step_cost;
+                if costs[neighbour].map_or(true, |known| next < known) {
+                    costs[neighbour] = Some(next);
+                    queue.push(Reverse((next, neighbour)));
                 }
+            }
+        }
+    }
+    costs
+}
+
+/// Builds a pathfinder over a small random grid of random terrain, with a
+/// passable origin and target that may or may not be connected.
+#[cfg(test)]
+fn random_pathfinder(seed: u64, geometry: Geometry, strategy: SearchStrategy) -> AStarPathfinder {
+    let mut seed_bytes = [0u8; 32];
+    for i in 0..8 {
+        seed_bytes[i] = (seed >> (8 * i)) as u8;
+    }
+    let mut rng = ChaChaRng::from_seed(seed_bytes);
+
+    let (width, height) = (rng.gen_range(2, 16), rng.gen_range(2, 16));
+    let mut map = Map::new(width, height, geometry);
+    for x in 0..width {
+        for y in 0..height {
+            let terrain = if rng.gen_bool(0.25) {
+                Terrain::Wall
+            } else {
+                Terrain::ALL[rng.gen_range(0, Terrain::ALL.len())]
+            };
+            map.set_terrain((x, y), terrain);
+        }
+    }
+
+    let origin = (rng.gen_range(0, width), rng.gen_range(0, height));
+    let target = (rng.gen_range(0, width), rng.gen_range(0, height));
+    map.set_terrain(origin, Terrain::Grass);
+    map.set_terrain(target, Terrain::Grass);
+    AStarPathfinder::new(map, origin, target, strategy)
+}
 
-                if (self_.width - x) + (self_.height - y) < 5 {
-                    continue;
+/// Sums the cost of each step along a path.
+#[cfg(test)]
+fn path_cost(pathfinder: &AStarPathfinder, path: &[Position]) -> Distance {
+    path.windows(2)
+        .map(|pair| pathfinder.map.step_cost(pair[0], pair[1]).unwrap())
+        .sum()
+}
+
+#[test]
+fn test_strategies_find_valid_paths() {
+    for geometry in Geometry::ALL.iter() {
+        for strategy in SearchStrategy::ALL.iter() {
+            let mut pathfinder = walled_pathfinder(*geometry, *strategy);
+            let path = pathfinder.get_path().into_path().expect("no path found");
+            assert_valid_path(&pathfinder, &path);
+        }
+    }
+}
+
+#[test]
+fn test_breadth_first_finds_fewest_steps() {
+    for geometry in Geometry::ALL.iter() {
+        let mut pathfinder = walled_pathfinder(*geometry, SearchStrategy::BreadthFirst);
+        let shortest_steps = flood_fill_steps(&pathfinder).unwrap();
+        let path = pathfinder.get_path().into_path().expect("no path found");
+        assert_valid_path(&pathfinder, &path);
+        assert_eq!(
+            path.len() - 1,
+            shortest_steps,
+            "path on {} not optimal",
+            geometry
+        );
+    }
+}
+
+#[test]
+fn test_optimal_strategies_find_cheapest_paths() {
+    for geometry in Geometry::ALL.iter() {
+        for strategy in [SearchStrategy::AStar, SearchStrategy::Dijkstra].iter() {
+            let mut pathfinder = walled_pathfinder(*geometry, *strategy);
+            let cheapest_cost = reference_cost(&pathfinder).unwrap();
+            let path = pathfinder.get_path().into_path().expect("no path found");
+            assert_valid_path(&pathfinder, &path);
+            assert_eq!(
+                path_cost(&pathfinder, &path),
+                cheapest_cost,
+                "{} path on {} not optimal",
+                strategy,
+                geometry
+            );
+        }
+    }
+}
+
+#[test]
+fn test_optimal_strategies_detour_along_roads() {
+    for geometry in Geometry::ALL.iter() {
+        for strategy in [SearchStrategy::AStar, SearchStrategy::Dijkstra].iter() {
+            // A band of steep mountains between origin and target, with a road around its end.
+            let mut map = Map::new(12, 10, *geometry);
+            let mut costs = TerrainCosts::default();
+            costs.set(Terrain::Mountain, Some(30));
+            map.set_costs(costs);
+            for y in 0..9 {
+                map.set_terrain((5, y), Terrain::Mountain);
+                map.set_terrain((6, y), Terrain::Mountain);
+            }
+            for x in 0..12 {
+                map.set_terrain((x, 9), Terrain::Road);
+            }
+            let mut pathfinder = AStarPathfinder::new(map, (1, 2), (10, 2), *strategy);
+            let cheapest_cost = reference_cost(&pathfinder).unwrap();
+            let path = pathfinder.get_path().into_path().expect("no path found");
+            assert_valid_path(&pathfinder, &path);
+            assert_eq!(path_cost(&pathfinder, &path), cheapest_cost);
+            assert!(path
+                .iter()
+                .all(|position| pathfinder.map.terrain()[*position] != Terrain::Mountain));
+        }
+    }
+}
+
+#[test]
+fn test_optimal_strategies_match_dijkstra_on_random_grids() {
+    for seed in 0..200 {
+        for geometry in Geometry::ALL.iter() {
+            for strategy in [SearchStrategy::AStar, SearchStrategy::Dijkstra].iter() {
+                let mut pathfinder = random_pathfinder(seed, *geometry, *strategy);
+                let result = pathfinder.get_path();
+                match reference_cost(&pathfinder) {
+                    Some(cheapest_cost) => {
+                        assert_eq!(result.outcome, Some(PathOutcome::Found), "seed {}", seed);
+                        assert_valid_path(&pathfinder, &result.path);
+                        assert_eq!(path_cost(&pathfinder, &result.path), result.cost);
+                        assert_eq!(
+                            result.cost, cheapest_cost,
+                            "{} path on {} not optimal for seed {}",
+                            strategy, geometry, seed
+                        );
+                    }
+                    None => assert_eq!(
+                        result.outcome,
+                        Some(PathOutcome::Unreachable),
+                        "seed {}",
+                        seed
+                    ),
                 }
+            }
+        }
+    }
+}
 
-                if ((x) ^ ((y + 3) % 2) ^ demo_iteration) % (1 + x / 2 + y / 5) <= 0 {
-                    array[(x, y)].state = AStarCellState::Blocked;
+#[test]
+fn test_breadth_first_matches_flood_fill_on_random_grids() {
+    for seed in 0..200 {
+        for geometry in Geometry::ALL.iter() {
+            let mut pathfinder = random_pathfinder(seed, *geometry, SearchStrategy::BreadthFirst);
+            let steps = pathfinder.get_path().into_path().map(|path| path.len() - 1);
+            assert_eq!(
+                steps,
+                flood_fill_steps(&pathfinder),
+                "path on {} not shortest for seed {}",
+                geometry,
+                seed
+            );
+        }
+    }
+}
+
+#[test]
+fn test_bidirectional_search_finds_valid_paths() {
+    for geometry in Geometry::ALL.iter() {
+        for strategy in SearchStrategy::ALL.iter() {
+            let mut pathfinder = walled_pathfinder(*geometry, *strategy);
+            pathfinder.set_bidirectional(true);
+            let result = pathfinder.get_path();
+            assert_eq!(result.cost, path_cost(&pathfinder, &result.path));
+            assert_valid_path(&pathfinder, &result.path);
+            assert!(pathfinder.backward_search().unwrap().closed((10, 2)));
+        }
+    }
+}
+
+#[test]
+fn test_bidirectional_search_matches_dijkstra_on_random_grids() {
+    for seed in 0..200 {
+        for geometry in Geometry::ALL.iter() {
+            for strategy in [SearchStrategy::AStar, SearchStrategy::Dijkstra].iter() {
+                let mut pathfinder = random_pathfinder(seed, *geometry, *strategy);
+                pathfinder.set_bidirectional(true);
+                let result = pathfinder.get_path();
+                match reference_cost(&pathfinder) {
+                    Some(cheapest_cost) => {
+                        assert_eq!(result.outcome, Some(PathOutcome::Found), "seed {}", seed);
+                        assert_valid_path(&pathfinder, &result.path);
+                        assert_eq!(path_cost(&pathfinder, &result.path), result.cost);
+                        assert_eq!(
+                            result.cost, cheapest_cost,
+                            "bidirectional {} path on {} not optimal for seed {}",
+                            strategy, geometry, seed
+                        );
+                    }
+                    None => assert_eq!(
+                        result.outcome,
+                        Some(PathOutcome::Unreachable),
+                        "seed {}",
+                        seed
+                    ),
                 }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_bidirectional_search_gives_up_early_on_enclosed_targets() {
+    // The target is shut in a five-by-five pocket in the corner of an open map.
+    let mut map = Map::new(64, 64, Geometry::Square8);
+    for i in 58..64 {
+        map.set_terrain((i, 58), Terrain::Wall);
+        map.set_terrain((58, i), Terrain::Wall);
+    }
+    let mut pathfinder = AStarPathfinder::new(map, (1, 1), (60, 60), SearchStrategy::AStar);
+    let unidirectional = pathfinder.get_path();
+    assert_eq!(unidirectional.outcome, Some(PathOutcome::Unreachable));
+
+    pathfinder.set_bidirectional(true);
+    assert!(pathfinder.bidirectional());
+    let bidirectional = pathfinder.get_path();
+    assert_eq!(bidirectional.outcome, Some(PathOutcome::Unreachable));
+    assert!(bidirectional.nodes_expanded < 2 * 25);
+    assert!(unidirectional.nodes_expanded > 3000);
+}
+
+#[test]
+fn test_components_reject_unreachable_targets_before_searching() {
+    let mut map = Map::new(64, 64, Geometry::Square8);
+    for i in 58..64 {
+        map.set_terrain((i, 58), Terrain::Wall);
+        map.set_terrain((58, i), Terrain::Wall);
+    }
+    let components = Components::new(&map);
+    let mut pathfinder = AStarPathfinder::new(map, (1, 1), (60, 60), SearchStrategy::AStar);
+    pathfinder.set_components(Some(components));
+    assert!(!pathfinder.working());
+    let result = pathfinder.get_path();
+    assert_eq!(result.outcome, Some(PathOutcome::Unreachable));
+    assert_eq!(result.nodes_expanded, 0);
+
+    // Only the reachable target holds up a search for costs.
+    let costs = pathfinder.costs_to((1, 1), &[(60, 60), (2, 1)]);
+    assert_eq!(costs[0], None);
+    assert!(costs[1].is_some());
+    assert!(!pathfinder.search().closed((30, 30)));
+
+    for seed in 0..100 {
+        for geometry in Geometry::ALL.iter() {
+            let mut pathfinder = random_pathfinder(seed, *geometry, SearchStrategy::AStar);
+            let expected = pathfinder.get_path().outcome;
+            let components = Components::new(pathfinder.map());
+            pathfinder.set_components(Some(components));
+            assert_eq!(pathfinder.get_path().outcome, expected, "seed {}", seed);
+        }
+    }
+}
+
+#[test]
+fn test_components_leave_impassable_origins_to_the_search() {
+    let mut map = Map::new(8, 8, Geometry::Square8);
+    map.set_terrain((1, 1), Terrain::Wall);
+    let mut pathfinder = AStarPathfinder::new(map, (1, 1), (6, 6), SearchStrategy::AStar);
+    let expected = pathfinder.get_path();
+    assert!(expected.found());
+
+    let components = Components::new(pathfinder.map());
+    pathfinder.set_components(Some(components));
+    assert_eq!(pathfinder.get_path(), expected);
+    assert_eq!(
+        pathfinder.costs_to((1, 1), &[(6, 6)])[0],
+        Some(expected.cost)
+    );
+}
+
+#[test]
+fn test_nearest_target_matches_cheapest_single_target_on_random_grids() {
+    for seed in 0..100 {
+        for geometry in Geometry::ALL.iter() {
+            for bidirectional in [false, true].iter() {
+                let mut pathfinder = random_pathfinder(seed, *geometry, SearchStrategy::AStar);
+                pathfinder.set_bidirectional(*bidirectional);
+                let mut rng = ChaChaRng::from_seed([seed as u8; 32]);
+                let (width, height) = (pathfinder.map.width(), pathfinder.map.height());
+                let targets: Vec<Position> = (0..4)
+                    .map(|_| (rng.gen_range(0, width), rng.gen_range(0, height)))
+                    .collect();
 
-                if ((x / 8) + ((y * 3) / 8)) % 5 == 0 {
-                    array[(x, y)].state = AStarCellState::Blocked;
+                let costs = reference_costs(&pathfinder);
+                let cheapest_cost = targets.iter().filter_map(|target| costs[*target]).min();
+                let origin = pathfinder.origin;
+                let result = pathfinder.find_nearest(origin, &targets);
+                match cheapest_cost {
+                    Some(cheapest_cost) => {
+                        assert_eq!(result.outcome, Some(PathOutcome::Found), "seed {}", seed);
+                        assert_valid_path(&pathfinder, &result.path);
+                        assert_eq!(path_cost(&pathfinder, &result.path), result.cost);
+                        assert_eq!(result.cost, cheapest_cost, "seed {}", seed);
+                    }
+                    None => assert_eq!(
+                        result.outcome,
+                        Some(PathOutcome::Unreachable),
+                        "seed {}",
+                        seed
+                    ),
                 }
             }
         }
+    }
+}
 
-        self_
+#[test]
+fn test_costs_to_many_targets_use_one_search() {
+    for seed in 0..100 {
+        for geometry in Geometry::ALL.iter() {
+            let mut pathfinder = random_pathfinder(seed, *geometry, SearchStrategy::Greedy);
+            let costs = reference_costs(&pathfinder);
+            let targets: Vec<Position> = costs.iter().map(|(position, _)| position).collect();
+            let expected: Vec<Option<Distance>> = costs.iter().map(|(_, cost)| *cost).collect();
+
+            let origin = pathfinder.origin;
+            assert_eq!(pathfinder.costs_to(origin, &targets), expected);
+            assert!(pathfinder.peek_path().nodes_expanded <= targets.len());
+            assert_eq!(pathfinder.strategy(), SearchStrategy::Greedy);
+        }
     }
+
+    let mut pathfinder = walled_pathfinder(Geometry::Square8, SearchStrategy::AStar);
+    assert_eq!(
+        pathfinder.costs_to((1, 2), &[(1, 2), (1, 3), (5, 0), (4, 2)]),
+        vec![Some(0), Some(20), None, Some(60)]
+    );
+    assert!(pathfinder.search().closed((10, 2)));
+
+    // The search stops as soon as every target has been reached.
+    assert_eq!(pathfinder.costs_to((1, 2), &[(4, 2)]), vec![Some(60)]);
+    assert!(!pathfinder.search().closed((10, 2)));
 }
 
-impl Default for AStarPathfinder {
-    fn default() -> Self {
-        let width = 64;
-        let height = 64;
-        let origin = (1, 1);
-        let target = (width - 2, height - 2);
-        Self {
-            width,
-            height,
-            origin,
-            target,
-            data: Array2D::<AStarCell>::new(width, height),
-            frontier: BinaryHeap::from(vec![AStarPath {
-                head: origin,
-                cost_from_origin: 0,
-                diagonless_distance_to_target: Self::diagonless_distance(origin, target),
-                min_cost_to_target: Self::min_distance(origin, target),
-            }]),
-            working: true,
+#[test]
+fn test_walled_square8_path_length() {
+    // Seven steps down to the gap in the wall at (5, 9), and seven steps back up.
+    let pathfinder = walled_pathfinder(Geometry::Square8, SearchStrategy::AStar);
+    assert_eq!(flood_fill_steps(&pathfinder), Some(14));
+}
+
+#[test]
+fn test_default_path_is_straight_diagonal() {
+    let mut pathfinder = AStarPathfinder::default();
+    let path = pathfinder.get_path().into_path().expect("no path found");
+    assert_eq!(path.len(), 62);
+    assert_eq!(path_cost(&pathfinder, &path), 61 * 2 * DIAGONAL_STEP_COST);
+}
+
+#[test]
+fn test_map_can_be_searched_repeatedly() {
+    let mut pathfinder = AStarPathfinder::demo(0, Geometry::Square8, SearchStrategy::AStar);
+    let queries = [((1, 1), (62, 62)), ((62, 62), (1, 1)), ((2, 1), (1, 2))];
+    for _ in 0..3 {
+        for (origin, target) in queries.iter() {
+            let path = pathfinder.find_path(*origin, *target);
+
+            let mut fresh = AStarPathfinder::new(
+                pathfinder.map().clone(),
+                *origin,
+                *target,
+                SearchStrategy::AStar,
+            );
+            assert_eq!(path, fresh.get_path());
+            assert_valid_path(&pathfinder, &path.path);
         }
     }
 }
+
+#[test]
+fn test_path_result_outcomes() {
+    let mut pathfinder = walled_pathfinder(Geometry::Square8, SearchStrategy::AStar);
+    let result = pathfinder.get_path();
+    assert_eq!(result.outcome, Some(PathOutcome::Found));
+    assert_eq!(result.cost, path_cost(&pathfinder, &result.path));
+    assert!(result.nodes_expanded >= result.path.len());
+    assert!(result.frontier_peak > 0);
+
+    pathfinder.set_budget(Some(5));
+    let result = pathfinder.find_path((1, 2), (10, 2));
+    assert_eq!(result.outcome, Some(PathOutcome::BudgetExceeded));
+    assert_eq!(result.nodes_expanded, 5);
+    assert_eq!(result.into_path(), None);
+
+    pathfinder.set_budget(None);
+    pathfinder.reset((1, 2), (10, 2));
+    pathfinder.step();
+    assert_eq!(pathfinder.peek_path().outcome, None);
+    pathfinder.abort();
+    assert!(!pathfinder.working());
+    assert_eq!(pathfinder.get_path().outcome, Some(PathOutcome::Aborted));
+
+    for y in 0..10 {
+        pathfinder.map_mut().set_terrain((5, y), Terrain::Wall);
+    }
+    let result = pathfinder.find_path((1, 2), (10, 2));
+    assert_eq!(result.outcome, Some(PathOutcome::Unreachable));
+    assert!(result.path.is_empty());
+    assert_eq!(result.nodes_expanded, 5 * 10);
+}
+
+#[test]
+fn test_stepping_in_batches_finds_the_same_path() {
+    let mut pathfinder = walled_pathfinder(Geometry::Hex, SearchStrategy::AStar);
+    let expected = pathfinder.get_path();
+
+    pathfinder.reset((1, 2), (10, 2));
+    let mut batches = 0;
+    while pathfinder.step_n(3) == SearchProgress::InProgress {
+        batches += 1;
+        let result = pathfinder.peek_path();
+        assert_eq!(result.outcome, None);
+        assert!(result.nodes_expanded <= 3 * batches);
+    }
+    assert!(batches > 1);
+    assert_eq!(pathfinder.peek_path(), expected);
+    assert_eq!(
+        pathfinder.step_n(3),
+        SearchProgress::Finished(PathOutcome::Found)
+    );
+
+    pathfinder.reset((1, 2), (10, 2));
+    assert_eq!(
+        pathfinder.step_for(Duration::from_secs(0)),
+        SearchProgress::InProgress
+    );
+    while pathfinder.step_for(Duration::from_millis(1)) == SearchProgress::InProgress {}
+    assert_eq!(pathfinder.peek_path(), expected);
+}
+
+#[test]
+fn test_paused_search_can_be_serialized_and_resumed() {
+    let mut pathfinder = walled_pathfinder(Geometry::Square8, SearchStrategy::AStar);
+    pathfinder.step_n(10);
+    assert!(pathfinder.working());
+
+    let json = serde_json::to_string(&pathfinder).unwrap();
+    let mut from_json: AStarPathfinder = serde_json::from_str(&json).unwrap();
+    let binary = bincode::serialize(&pathfinder).unwrap();
+    let mut from_binary: AStarPathfinder = bincode::deserialize(&binary).unwrap();
+
+    let expected = pathfinder.get_path();
+    assert_eq!(expected.outcome, Some(PathOutcome::Found));
+    assert_eq!(from_json.get_path(), expected);
+    assert_eq!(from_binary.get_path(), expected);
+}
+
+#[test]
+fn test_malformed_searches_are_rejected() {
+    let grid: Result<Array2D<u8>, _> =
+        serde_json::from_str(r#"{"width":2,"height":2,"values":[]}"#);
+    assert!(grid.is_err());
+    let grid: Array2D<u8> =
+        serde_json::from_str(r#"{"width":2,"height":1,"values":[1,2]}"#).unwrap();
+    assert_eq!(grid[(1, 0)], 2);
+
+    // A search moved onto a smaller map, or pointed off the edge of its own.
+    let pathfinder = walled_pathfinder(Geometry::Square8, SearchStrategy::AStar);
+    let mut smaller = pathfinder.clone();
+    smaller.map = Map::new(4, 4, Geometry::Square8);
+    smaller.origin = (0, 0);
+    smaller.targets = vec![(3, 3)];
+    let mut outside = pathfinder.clone();
+    outside.targets = vec![(40, 2)];
+    for bad in [smaller, outside].iter() {
+        let json = serde_json::to_string(bad).unwrap();
+        assert!(serde_json::from_str::<AStarPathfinder>(&json).is_err());
+    }
+    let json = serde_json::to_string(&pathfinder).unwrap();
+    assert!(serde_json::from_str::<AStarPathfinder>(&json).is_ok());
+}
+
+#[test]
+fn test_search_state_reset_forgets_cells() {
+    let mut search = SearchState::new((4, 4));
+    search.close((1, 2), (1, 1), 10);
+    assert_eq!(search.parent((1, 2)), Some((1, 1)));
+    assert_eq!(search.cost((1, 2)), Some(10));
+
+    assert!(!search.relax((1, 2), (1, 1), 5));
+    assert!(search.relax((2, 2), (1, 2), 20));
+    assert!(!search.relax((2, 2), (1, 1), 20));
+    assert!(search.relax((2, 2), (1, 1), 14));
+    assert_eq!(search.best_cost((2, 2)), Some(14));
+    assert_eq!(search.parent((2, 2)), None);
+
+    search.reset();
+    assert!(!search.closed((1, 2)));
+    assert_eq!(search.parent((1, 2)), None);
+
+    search.generation = u32::max_value();
+    search.close((3, 3), (2, 2), 14);
+    search.reset();
+    assert!(!search.closed((3, 3)));
+}
@@ -0,0 +1,496 @@
+//! Rasterising [Output] frames into images, and saving them as PNGs.
+
+use std::{fs::File, io, io::BufWriter, io::Write, path::Path};
+
+use png::HasParameters;
+
+use super::output::{parse_color, Output};
+
+/// The number of samples along each axis of a pixel that shape edges are
+/// smoothed with. [SampleMask] needs a bit for each of them.
+const SAMPLES: usize = 4;
+
+/// Which of a pixel's samples a shape covers, one bit each.
+type SampleMask = u16;
+
+/// An image with straight (not premultiplied) alpha.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Raster {
+    width: usize,
+    height: usize,
+    /// Red, green and blue from 0 to 255 and alpha from 0 to 1, row by row.
+    pixels: Vec<[f32; 4]>,
+}
+
+impl Raster {
+    /// A transparent image.
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![[0.; 4]; width * height],
+        }
+    }
+
+    /// Rasterises a frame onto a transparent image the size of the frame,
+    /// drawing each layer's shapes in the same order the canvas does.
+    ///
+    /// Text is drawn with a small built-in bitmap font of the letters A to Z
+    /// and digits, so other characters are left as spaces.
+    pub fn render(output: &Output) -> Self {
+        let mut raster = Self::new(output.width as usize, output.height as usize);
+        raster.draw(output);
+        raster
+    }
+
+    /// Fills the whole image with a colour, such as to give a frame an
+    /// opaque background before it's drawn.
+    pub fn fill(&mut self, color: &str) {
+        let (width, height) = (self.width as f64, self.height as f64);
+        self.fill_rect(0., 0., width, height, color);
+    }
+
+    /// Draws a frame over the image.
+    pub fn draw(&mut self, output: &Output) {
+        for layer in &output.layers {
+            for rect in &layer.rects {
+                self.fill_rect(rect.x, rect.y, rect.width, rect.height, rect.color);
+            }
+            for polygon in &layer.polygons {
+                let points = &polygon.points;
+                self.fill_shape(bounds(points, 0.), polygon.color, |x, y| {
+                    inside_polygon(points, x, y)
+                });
+            }
+            for line in &layer.lines {
+                self.fill_line(&line.points, line.width / 2., line.color);
+            }
+            for circle in &layer.circles {
+                let (cx, cy, radius) = (circle.x, circle.y, circle.radius);
+                let bounds = (cx - radius, cy - radius, cx + radius, cy + radius);
+                self.fill_shape(bounds, circle.color, |x, y| {
+                    (x - cx).powi(2) + (y - cy).powi(2) <= radius * radius
+                });
+            }
+            for text in &layer.texts {
+                self.draw_text(&text.text, text.x, text.y, text.size, text.color);
+            }
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The colour of a pixel, as red, green, blue and alpha bytes.
+    pub fn pixel(&self, x: usize, y: usize) -> [u8; 4] {
+        let [r, g, b, a] = self.pixels[y * self.width + x];
+        [
+            r.round() as u8,
+            g.round() as u8,
+            b.round() as u8,
+            (a * 255.).round() as u8,
+        ]
+    }
+
+    /// Every pixel as red, green, blue and alpha bytes, row by row.
+    pub fn to_rgba(&self) -> Vec<u8> {
+        (0..self.height)
+            .flat_map(|y| (0..self.width).flat_map(move |x| self.pixel(x, y).to_vec()))
+            .collect()
+    }
+
+    /// Writes the image as a PNG.
+    pub fn write_png<W: Write>(&self, writer: W) -> io::Result<()> {
+        let mut encoder = png::Encoder::new(writer, self.width as u32, self.height as u32);
+        encoder.set(png::ColorType::RGBA).set(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.to_rgba())?;
+        Ok(())
+    }
+
+    /// Saves the image as a PNG file.
+    pub fn save_png(&self, path: &Path) -> io::Result<()> {
+        self.write_png(BufWriter::new(File::create(path)?))
+    }
+
+    /// Blends a colour over a pixel, covering the given fraction of it.
+    fn blend(&mut self, x: usize, y: usize, [r, g, b, a]: [f32; 4], coverage: f64) {
+        let alpha = a * coverage as f32;
+        if alpha <= 0. {
+            return;
+        }
+        let pixel = &mut self.pixels[y * self.width + x];
+        let below = pixel[3] * (1. - alpha);
+        let out = alpha + below;
+        for (channel, source) in pixel.iter_mut().zip(&[r, g, b]) {
+            *channel = (source * alpha + *channel * below) / out;
+        }
+        pixel[3] = out;
+    }
+
+    /// The pixels that a box from `(left, top)` to `(right, bottom)` touches.
+    fn pixel_range(
+        &self,
+        (left, top, right, bottom): (f64, f64, f64, f64),
+    ) -> (usize, usize, usize, usize) {
+        let clamp = |value: f64, limit: usize| value.max(0.).min(limit as f64) as usize;
+        (
+            clamp(left.floor(), self.width),
+            clamp(top.floor(), self.height),
+            clamp(right.ceil(), self.width),
+            clamp(bottom.ceil(), self.height),
+        )
+    }
+
+    /// Fills a rect, covering the pixels along its edges by exactly the
+    /// fraction of them that it overlaps.
+    fn fill_rect(&mut self, left: f64, top: f64, width: f64, height: f64, color: &str) {
+        let color = parse_color(color);
+        let (right, bottom) = (left + width, top + height);
+        let (x0, y0, x1, y1) = self.pixel_range((left, top, right, bottom));
+        let overlap = |start: f64, end: f64, pixel: usize| {
+            (end.min(pixel as f64 + 1.) - start.max(pixel as f64)).max(0.)
+        };
+        for y in y0..y1 {
+            for x in x0..x1 {
+                let coverage = overlap(left, right, x) * overlap(top, bottom, y);
+                self.blend(x, y, color, coverage);
+            }
+        }
+    }
+
+    /// Fills the points within `bounds` that `inside` is true of, smoothing
+    /// the edges by sampling several points in each pixel.
+    fn fill_shape<F>(&mut self, bounds: (f64, f64, f64, f64), color: &str, inside: F)
+    where
+        F: Fn(f64, f64) -> bool,
+    {
+        let color = parse_color(color);
+        let (x0, y0, x1, y1) = self.pixel_range(bounds);
+        for y in y0..y1 {
+            for x in x0..x1 {
+                self.blend(x, y, color, coverage(sample_mask(x, y, &inside)));
+            }
+        }
+    }
+
+    /// Fills the points within `radius` of a line through `points`. Each
+    /// segment is only sampled over the pixels around it, and each pixel is
+    /// blended once however many segments cover it, so that the joins of
+    /// translucent lines aren't darker than the rest.
+    fn fill_line(&mut self, points: &[(f64, f64)], radius: f64, color: &str) {
+        if points.is_empty() {
+            return;
+        }
+        let color = parse_color(color);
+        let (x0, y0, x1, y1) = self.pixel_range(bounds(points, radius));
+        let width = x1 - x0;
+        let mut masks = vec![0; width * (y1 - y0)];
+        for segment in points.windows(2) {
+            let (a, b) = (segment[0], segment[1]);
+            let (sx0, sy0, sx1, sy1) = self.pixel_range(bounds(segment, radius));
+            for y in sy0..sy1 {
+                for x in sx0..sx1 {
+                    masks[(y - y0) * width + x - x0] |=
+                        sample_mask(x, y, |x, y| segment_distance(a, b, x, y) <= radius);
+                }
+            }
+        }
+        for (i, mask) in masks.into_iter().enumerate() {
+            self.blend(x0 + i % width, y0 + i / width, color, coverage(mask));
+        }
+    }
+
+    /// Draws a line of text centred on a point, with its capital letters
+    /// about as tall as the canvas would draw them at the same size.
+    fn draw_text(&mut self, text: &str, x: f64, y: f64, size: f64, color: &str) {
+        let unit = size * 0.7 / GLYPH_HEIGHT as f64;
+        let advance = (GLYPH_WIDTH + 1) as f64;
+        let columns = text.chars().count() as f64 * advance - 1.;
+        let left = x - columns * unit / 2.;
+        let top = y - GLYPH_HEIGHT as f64 * unit / 2.;
+        for (i, c) in text.chars().enumerate() {
+            let rows = match glyph(c) {
+                Some(rows) => rows,
+                None => continue,
+            };
+            for (row, bits) in rows.iter().enumerate() {
+                for column in 0..GLYPH_WIDTH {
+                    if bits & (1 << (GLYPH_WIDTH - 1 - column)) != 0 {
+                        self.fill_rect(
+                            left + (i as f64 * advance + column as f64) * unit,
+                            top + row as f64 * unit,
+                            unit,
+                            unit,
+                            color,
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Which of the samples in a pixel `inside` is true of.
+fn sample_mask<F>(x: usize, y: usize, inside: F) -> SampleMask
+where
+    F: Fn(f64, f64) -> bool,
+{
+    let mut mask = 0;
+    for i in 0..SAMPLES {
+        for j in 0..SAMPLES {
+            let sx = x as f64 + (i as f64 + 0.5) / SAMPLES as f64;
+            let sy = y as f64 + (j as f64 + 0.5) / SAMPLES as f64;
+            if inside(sx, sy) {
+                mask |= 1 << (i * SAMPLES + j);
+            }
+        }
+    }
+    mask
+}
+
+/// The fraction of a pixel that a shape covering some of its samples covers.
+fn coverage(mask: SampleMask) -> f64 {
+    f64::from(mask.count_ones()) / (SAMPLES * SAMPLES) as f64
+}
+
+/// The box around some points, grown by a margin, as left, top, right and bottom.
+fn bounds(points: &[(f64, f64)], margin: f64) -> (f64, f64, f64, f64) {
+    points.iter().fold(
+        (
+            std::f64::INFINITY,
+            std::f64::INFINITY,
+            std::f64::NEG_INFINITY,
+            std::f64::NEG_INFINITY,
+        ),
+        |(left, top, right, bottom), (x, y)| {
+            (
+                left.min(x - margin),
+                top.min(y - margin),
+                right.max(x + margin),
+                bottom.max(y + margin),
+            )
+        },
+    )
+}
+
+/// Whether a point is inside a polygon, by the even-odd rule.
+fn inside_polygon(points: &[(f64, f64)], x: f64, y: f64) -> bool {
+    let mut inside = false;
+    let mut previous = match points.last() {
+        Some(point) => *point,
+        None => return false,
+    };
+    for &(px, py) in points {
+        let (qx, qy) = previous;
+        if (py > y) != (qy > y) && x < (qx - px) * (y - py) / (qy - py) + px {
+            inside = !inside;
+        }
+        previous = (px, py);
+    }
+    inside
+}
+
+/// The distance from a point to the closest point on a line segment.
+fn segment_distance(a: (f64, f64), b: (f64, f64), x: f64, y: f64) -> f64 {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let length_squared = dx * dx + dy * dy;
+    let t = if length_squared == 0. {
+        0.
+    } else {
+        (((x - a.0) * dx + (y - a.1) * dy) / length_squared)
+            .max(0.)
+            .min(1.)
+    };
+    ((a.0 + t * dx - x).powi(2) + (a.1 + t * dy - y).powi(2)).sqrt()
+}
+
+const GLYPH_WIDTH: usize = 5;
+const GLYPH_HEIGHT: usize = 7;
+
+/// The rows of a character in the built-in font, from the top, with the
+/// leftmost column in the highest bit.
+fn glyph(c: char) -> Option<[u8; GLYPH_HEIGHT]> {
+    Some(match c.to_ascii_uppercase() {
+        'A' => [
+            0b01110, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001,
+        ],
+        'B' => [
+            0b11110, 0b10001, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110,
+        ],
+        'C' => [
+            0b01110, 0b10001, 0b10000, 0b10000, 0b10000, 0b10001, 0b01110,
+        ],
+        'D' => [
+            0b11110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b11110,
+        ],
+        'E' => [
+            0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111,
+        ],
+        'F' => [
+            0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000,
+        ],
+        'G' => [
+            0b01110, 0b10001, 0b10000, 0b10111, 0b10001, 0b10001, 0b01111,
+        ],
+        'H' => [
+            0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001,
+        ],
+        'I' => [
+            0b01110, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110,
+        ],
+        'J' => [
+            0b00111, 0b00010, 0b00010, 0b00010, 0b00010, 0b10010, 0b01100,
+        ],
+        'K' => [
+            0b10001, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010, 0b10001,
+        ],
+        'L' => [
+            0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b11111,
+        ],
+        'M' => [
+            0b10001, 0b11011, 0b10101, 0b10101, 0b10001, 0b10001, 0b10001,
+        ],
+        'N' => [
+            0b10001, 0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001,
+        ],
+        'O' => [
+            0b01110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110,
+        ],
+        'P' => [
+            0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000, 0b10000,
+        ],
+        'Q' => [
+            0b01110, 0b10001, 0b10001, 0b10001, 0b10101, 0b10010, 0b01101,
+        ],
+        'R' => [
+            0b11110, 0b10001, 0b10001, 0b11110, 0b10100, 0b10010, 0b10001,
+        ],
+        'S' => [
+            0b01111, 0b10000, 0b10000, 0b01110, 0b00001, 0b00001, 0b11110,
+        ],
+        'T' => [
+            0b11111, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100,
+        ],
+        'U' => [
+            0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110,
+        ],
+        'V' => [
+            0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100,
+        ],
+        'W' => [
+            0b10001, 0b10001, 0b10001, 0b10101, 0b10101, 0b10101, 0b01010,
+        ],
+        'X' => [
+            0b10001, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001, 0b10001,
+        ],
+        'Y' => [
+            0b10001, 0b10001, 0b10001, 0b01010, 0b00100, 0b00100, 0b00100,
+        ],
+        'Z' => [
+            0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b11111,
+        ],
+        '0' => [
+            0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110,
+        ],
+        '1' => [
+            0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110,
+        ],
+        '2' => [
+            0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111,
+        ],
+        '3' => [
+            0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110,
+        ],
+        '4' => [
+            0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010,
+        ],
+        '5' => [
+            0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110,
+        ],
+        '6' => [
+            0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110,
+        ],
+        '7' => [
+            0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000,
+        ],
+        '8' => [
+            0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110,
+        ],
+        '9' => [
+            0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100,
+        ],
+        _ => return None,
+    })
+}
+
+#[test]
+fn test_shapes() {
+    let mut raster = Raster::new(4, 4);
+    raster.fill_rect(1., 1., 2., 1.5, "rgb(255, 0, 0)");
+    assert_eq!(raster.pixel(0, 0), [0, 0, 0, 0]);
+    assert_eq!(raster.pixel(1, 1), [255, 0, 0, 255]);
+    assert_eq!(raster.pixel(2, 2), [255, 0, 0, 128]);
+
+    // Half-transparent blue over opaque red is an even mix.
+    raster.fill_rect(0., 0., 4., 4., "rgba(0, 0, 255, 0.5)");
+    assert_eq!(raster.pixel(1, 1), [128, 0, 128, 255]);
+    assert_eq!(raster.pixel(0, 0), [0, 0, 255, 128]);
+
+    // Where a translucent line's segments overlap, it's only drawn once.
+    let mut raster = Raster::new(8, 8);
+    raster.fill_line(&[(1., 2.), (5., 2.), (5., 6.)], 1., "rgba(0, 0, 255, 0.5)");
+    assert_eq!(raster.pixel(4, 1), [0, 0, 255, 128]);
+    assert_eq!(raster.pixel(4, 2), [0, 0, 255, 128]);
+    assert_eq!(raster.pixel(7, 7), [0, 0, 0, 0]);
+
+    // A line with no points, such as the path of a search with no route,
+    // draws nothing.
+    let before = raster.clone();
+    raster.fill_line(&[], 1., "rgba(0, 0, 255, 0.5)");
+    assert!(raster == before);
+
+    assert!(inside_polygon(&[(0., 0.), (4., 0.), (0., 4.)], 1., 1.));
+    assert!(!inside_polygon(&[(0., 0.), (4., 0.), (0., 4.)], 3., 3.));
+    assert_eq!(segment_distance((0., 0.), (4., 0.), 2., 3.), 3.);
+    assert_eq!(segment_distance((0., 0.), (4., 0.), 7., 4.), 5.);
+}
+
+#[test]
+fn test_png_matches_golden_images() {
+    use super::drawing::{example_frame, golden_path};
+    use super::topology::Geometry;
+
+    for (geometry, name) in [
+        (Geometry::Square8, "search.png"),
+        (Geometry::Hex, "hex.png"),
+    ]
+    .iter()
+    {
+        let raster = Raster::render(&example_frame(*geometry));
+        let (path, bless) = golden_path(name);
+        if bless {
+            raster.save_png(&path).unwrap();
+        }
+
+        let (info, mut reader) = png::Decoder::new(File::open(&path).unwrap())
+            .read_info()
+            .unwrap();
+        let mut golden = vec![0; info.buffer_size()];
+        reader.next_frame(&mut golden).unwrap();
+        assert_eq!(
+            (info.width as usize, info.height as usize),
+            (raster.width(), raster.height())
+        );
+        assert!(
+            raster.to_rgba() == golden,
+            "{} differs; rerun with TPRIME_BLESS=1 to update it if that's intended",
+            path.display()
+        );
+    }
+}
@@ -0,0 +1,326 @@
+//! A packed binary encoding of [Output] frames, which is kept in memory for
+//! the canvas to read directly instead of being serialized for every frame.
+//!
+//! A frame is a flat list of `f32`s: the width, height and number of layers,
+//! then each layer as a [LayerMode], the number of shapes that follow, and
+//! the shapes. Each shape is its [Shape] code, its colour as red, green, blue
+//! (0–255) and alpha (0–1), and then:
+//!
+//! - a rect: its left, top, width and height
+//! - a polygon: the number of points, then the x and y of each
+//! - a line: its width, the number of points, then the x and y of each
+//! - a circle: the x and y of its centre, and its radius
+//! - text: the x and y of its centre, its size, the number of characters,
+//!   then the code point of each
+//!
+//! Retained layers only include the shapes that have changed since the last
+//! frame, so once a map has been drawn, only the cells that change are sent.
+
+use super::output::{parse_color, Output, OutputLayer};
+
+/// How a layer's shapes are drawn.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LayerMode {
+    /// The layer is drawn from scratch every frame.
+    Transient = 0,
+    /// The shapes are drawn over the layer as it was last frame, replacing
+    /// the ones in the same place.
+    Update = 1,
+    /// The layer is cleared and then kept for the following frames.
+    Replace = 2,
+}
+
+/// The kind of each shape.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shape {
+    Rect = 1,
+    Polygon = 2,
+    Line = 3,
+    Circle = 4,
+    Text = 5,
+}
+
+/// The shapes of one layer, encoded one after another.
+#[derive(Clone, Default)]
+struct EncodedLayer {
+    name: &'static str,
+    data: Vec<f32>,
+    /// Where each shape ends in `data`.
+    ends: Vec<usize>,
+}
+
+impl EncodedLayer {
+    /// The encoding of each shape.
+    fn shapes(&self) -> impl Iterator<Item = &[f32]> {
+        let starts = Some(0).into_iter().chain(self.ends.iter().cloned());
+        starts
+            .zip(self.ends.iter())
+            .map(move |(start, end)| &self.data[start..*end])
+    }
+
+    fn encode(&mut self, layer: &OutputLayer) {
+        self.name = layer.name;
+        self.data.clear();
+        self.ends.clear();
+
+        for rect in &layer.rects {
+            self.shape(Shape::Rect, rect.color);
+            self.values(&[rect.x, rect.y, rect.width, rect.height]);
+            self.ends.push(self.data.len());
+        }
+        for polygon in &layer.polygons {
+            self.shape(Shape::Polygon, polygon.color);
+            self.points(&polygon.points);
+            self.ends.push(self.data.len());
+        }
+        for line in &layer.lines {
+            self.shape(Shape::Line, line.color);
+            self.data.push(line.width as f32);
+            self.points(&line.points);
+            self.ends.push(self.data.len());
+        }
+        for circle in &layer.circles {
+            self.shape(Shape::Circle, circle.color);
+            self.values(&[circle.x, circle.y, circle.radius]);
+            self.ends.push(self.data.len());
+        }
+        for text in &layer.texts {
+            self.shape(Shape::Text, text.color);
+            self.values(&[text.x, text.y, text.size]);
+            self.data.push(text.text.chars().count() as f32);
+            self.data.extend(text.text.chars().map(|c| c as u32 as f32));
+            self.ends.push(self.data.len());
+        }
+    }
+
+    fn shape(&mut self, shape: Shape, color: &str) {
+        self.data.push(shape as u32 as f32);
+        self.data.extend(&parse_color(color));
+    }
+
+    fn values(&mut self, values: &[f64]) {
+        self.data.extend(values.iter().map(|value| *value as f32));
+    }
+
+    fn points(&mut self, points: &[(f64, f64)]) {
+        self.data.push(points.len() as f32);
+        for (x, y) in points {
+            self.data.push(*x as f32);
+            self.data.push(*y as f32);
+        }
+    }
+}
+
+/// The encoding of the latest frame, along with the retained layers of the
+/// one before it that the next frame's are compared against.
+#[derive(Default)]
+pub struct RenderBuffer {
+    data: Vec<f32>,
+    size: (u32, u32),
+    /// The retained layers as they were last drawn.
+    retained: Vec<EncodedLayer>,
+    /// Scratch space for encoding each layer.
+    current: EncodedLayer,
+}
+
+impl RenderBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The encoding of the latest frame.
+    pub fn data(&self) -> &[f32] {
+        &self.data
+    }
+
+    /// Forgets the retained layers, so the next frame is drawn in full, such
+    /// as when whatever's drawing the frames has lost them.
+    pub fn reset(&mut self) {
+        self.retained.clear();
+    }
+
+    /// Encodes a frame, replacing the last one.
+    pub fn encode(&mut self, output: &Output) {
+        let resized = self.size != (output.width, output.height);
+        self.size = (output.width, output.height);
+        self.data.clear();
+        self.data.extend(&[
+            output.width as f32,
+            output.height as f32,
+            output.layers.len() as f32,
+        ]);
+
+        let mut retained = Vec::new();
+        for layer in &output.layers {
+            let previous = match self
+                .retained
+                .iter()
+                .position(|previous| previous.name == layer.name)
+            {
+                Some(index) => Some(self.retained.swap_remove(index)),
+                None => None,
+            };
+            self.current.encode(layer);
+            let current = &self.current;
+
+            if !layer.retained {
+                self.data.push(LayerMode::Transient as u32 as f32);
+                self.data.push(current.ends.len() as f32);
+                self.data.extend(&current.data);
+                continue;
+            }
+
+            // Only colours can change in place; if any shape has moved, the
+            // layer has to be drawn again from scratch.
+            let changes: Option<Vec<&[f32]>> = match &previous {
+                Some(previous) if !resized && previous.ends.len() == current.ends.len() => {
+                    let mut changes = Vec::new();
+                    let mut moved = false;
+                    for (shape, last) in current.shapes().zip(previous.shapes()) {
+                        if shape != last {
+                            moved |= shape[0] != last[0] || shape[5..] != last[5..];
+                            changes.push(shape);
+                        }
+                    }
+                    if moved {
+                        None
+                    } else {
+                        Some(changes)
+                    }
+                }
+                _ => None,
+            };
+
+            match changes {
+                Some(changes) => {
+                    self.data.push(LayerMode::Update as u32 as f32);
+                    self.data.push(changes.len() as f32);
+                    for shape in changes {
+                        self.data.extend(shape);
+                    }
+                }
+                None => {
+                    self.data.push(LayerMode::Replace as u32 as f32);
+                    self.data.push(current.ends.len() as f32);
+                    self.data.extend(&current.data);
+                }
+            }
+
+            // Keep this frame's encoding, and reuse the old one's allocation
+            // for the next layer.
+            let mut kept = previous.unwrap_or_default();
+            std::mem::swap(&mut kept, &mut self.current);
+            retained.push(kept);
+        }
+        self.retained = retained;
+    }
+}
+
+#[cfg(test)]
+use super::output::{OutputLine, OutputRect, OutputText};
+
+/// Decodes each layer's mode and shapes from a frame, as the canvas does.
+#[cfg(test)]
+fn decode(data: &[f32]) -> Vec<(f32, Vec<Vec<f32>>)> {
+    let mut layers = Vec::new();
+    let mut i = 3;
+    for _ in 0..data[2] as usize {
+        let (mode, count) = (data[i], data[i + 1] as usize);
+        i += 2;
+        let mut shapes = Vec::new();
+        for _ in 0..count {
+            let start = i;
+            i += 5;
+            i += match data[start] as u32 {
+                1 => 4,
+                2 => 1 + 2 * data[i] as usize,
+                3 => 2 + 2 * data[i + 1] as usize,
+                4 => 3,
+                5 => 4 + data[i + 3] as usize,
+                shape => panic!("unknown shape {}", shape),
+            };
+            shapes.push(data[start..i].to_vec());
+        }
+        layers.push((mode, shapes));
+    }
+    assert_eq!(i, data.len());
+    layers
+}
+
+#[cfg(test)]
+fn test_frame(colors: &[&'static str]) -> Output {
+    let mut output = Output::new(&["cells", "overlay"]);
+    output.width = 64;
+    output.height = 16;
+    let cells = output.layer("cells");
+    cells.retained = true;
+    for (i, color) in colors.iter().enumerate() {
+        cells.rects.push(OutputRect {
+            color,
+            x: 16. * i as f64,
+            y: 0.,
+            width: 16.,
+            height: 16.,
+        });
+    }
+    let overlay = output.layer("overlay");
+    overlay.lines.push(OutputLine {
+        color: "rgba(64, 192, 64, 0.5)",
+        width: 2.,
+        points: vec![(8., 8.), (56., 8.)],
+    });
+    overlay.texts.push(OutputText {
+        color: "rgb(248, 248, 248)",
+        x: 8.,
+        y: 8.,
+        size: 12.,
+        text: "S′".to_string(),
+    });
+    output
+}
+
+#[test]
+fn test_retained_layers_only_send_changed_shapes() {
+    let red = "rgba(192, 0, 64, 1.0)";
+    let green = "rgba(40, 72, 40, 1.0)";
+    let mut buffer = RenderBuffer::new();
+
+    buffer.encode(&test_frame(&[red, red, green, green]));
+    let layers = decode(buffer.data());
+    assert_eq!(&buffer.data()[..3], &[64., 16., 2.]);
+    assert_eq!(layers[0].0, LayerMode::Replace as u32 as f32);
+    assert_eq!(layers[0].1.len(), 4);
+    assert_eq!(layers[1].0, LayerMode::Transient as u32 as f32);
+    assert_eq!(
+        layers[1].1,
+        vec![
+            vec![3., 64., 192., 64., 0.5, 2., 2., 8., 8., 56., 8.],
+            vec![5., 248., 248., 248., 1., 8., 8., 12., 2., 83., 8242.],
+        ]
+    );
+
+    // The same frame again sends none of the cells, but all of the overlay.
+    buffer.encode(&test_frame(&[red, red, green, green]));
+    let layers = decode(buffer.data());
+    assert_eq!(layers[0].0, LayerMode::Update as u32 as f32);
+    assert!(layers[0].1.is_empty());
+    assert_eq!(layers[1].1.len(), 2);
+
+    // Changing a cell sends only that cell.
+    buffer.encode(&test_frame(&[red, green, green, green]));
+    let layers = decode(buffer.data());
+    assert_eq!(layers[0].0, LayerMode::Update as u32 as f32);
+    assert_eq!(
+        layers[0].1,
+        vec![vec![1., 40., 72., 40., 1., 16., 0., 16., 16.]]
+    );
+
+    // Adding a cell, or forgetting what was drawn, sends them all again.
+    buffer.encode(&test_frame(&[red, green, green, green, red]));
+    assert_eq!(decode(buffer.data())[0].0, LayerMode::Replace as u32 as f32);
+    buffer.reset();
+    buffer.encode(&test_frame(&[red, green, green, green, red]));
+    assert_eq!(decode(buffer.data())[0].0, LayerMode::Replace as u32 as f32);
+    buffer.encode(&test_frame(&[red, green, green, green, red]));
+    assert_eq!(decode(buffer.data())[0].1.len(), 0);
+}
@@ -0,0 +1,145 @@
+//! Rendering [Output] frames as SVG documents.
+
+use std::fmt::Write;
+
+use super::output::{parse_color, Output};
+
+/// Renders a frame as a standalone SVG document, with a group for each layer.
+pub fn render_svg(output: &Output) -> String {
+    let mut svg = String::new();
+    writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}">"#,
+        w = output.width,
+        h = output.height
+    ).unwrap();
+
+    for layer in &output.layers {
+        writeln!(svg, r#"<g id="{}">"#, escape(layer.name)).unwrap();
+        for rect in &layer.rects {
+            writeln!(
+                svg,
+                r#"<rect x="{}" y="{}" width="{}" height="{}" {}/>"#,
+                number(rect.x),
+                number(rect.y),
+                number(rect.width),
+                number(rect.height),
+                paint("fill", rect.color)
+            )
+            .unwrap();
+        }
+        for polygon in &layer.polygons {
+            writeln!(
+                svg,
+                r#"<polygon points="{}" {}/>"#,
+                points(&polygon.points),
+                paint("fill", polygon.color)
+            )
+            .unwrap();
+        }
+        for line in &layer.lines {
+            writeln!(
+                svg,
+                r#"<polyline points="{}" fill="none" {} stroke-width="{}" stroke-linecap="round" stroke-linejoin="round"/>"#,
+                points(&line.points),
+                paint("stroke", line.color),
+                number(line.width)
+            ).unwrap();
+        }
+        for circle in &layer.circles {
+            writeln!(
+                svg,
+                r#"<circle cx="{}" cy="{}" r="{}" {}/>"#,
+                number(circle.x),
+                number(circle.y),
+                number(circle.radius),
+                paint("fill", circle.color)
+            )
+            .unwrap();
+        }
+        for text in &layer.texts {
+            writeln!(
+                svg,
+                r#"<text x="{}" y="{}" font-size="{}" font-family="sans-serif" font-weight="bold" text-anchor="middle" dominant-baseline="central" {}>{}</text>"#,
+                number(text.x),
+                number(text.y),
+                number(text.size),
+                paint("fill", text.color),
+                escape(&text.text)
+            ).unwrap();
+        }
+        writeln!(svg, "</g>").unwrap();
+    }
+
+    writeln!(svg, "</svg>").unwrap();
+    svg
+}
+
+/// Formats a coordinate to two decimal places, without trailing zeros.
+fn number(value: f64) -> String {
+    let text = format!("{:.2}", value);
+    let text = text.trim_end_matches('0').trim_end_matches('.');
+    match text {
+        "-0" | "" => "0".to_string(),
+        _ => text.to_string(),
+    }
+}
+
+fn points(points: &[(f64, f64)]) -> String {
+    points
+        .iter()
+        .map(|(x, y)| format!("{},{}", number(*x), number(*y)))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// The attributes that fill or stroke a shape with a colour. SVG 1.1 doesn't
+/// take `rgba()` colours, so the alpha is given as a separate opacity.
+fn paint(attribute: &str, color: &str) -> String {
+    let [r, g, b, a] = parse_color(color);
+    let mut paint = format!(r#"{}="rgb({}, {}, {})""#, attribute, r, g, b);
+    if a < 1. {
+        write!(
+            paint,
+            r#" {}-opacity="{}""#,
+            attribute,
+            number(f64::from(a))
+        )
+        .unwrap();
+    }
+    paint
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[test]
+fn test_number() {
+    assert_eq!(number(8.), "8");
+    assert_eq!(number(7.6), "7.6");
+    assert_eq!(number(1. / 3.), "0.33");
+    assert_eq!(number(-0.001), "0");
+}
+
+#[test]
+fn test_svg_matches_golden_image() {
+    use super::drawing::{example_frame, golden_path};
+    use super::topology::Geometry;
+
+    let svg = render_svg(&example_frame(Geometry::Square8));
+    let (path, bless) = golden_path("search.svg");
+    if bless {
+        std::fs::write(&path, &svg).unwrap();
+    }
+    let golden = std::fs::read_to_string(&path).unwrap();
+    assert!(
+        svg == golden,
+        "{} differs; rerun with TPRIME_BLESS=1 to update it if that's intended",
+        path.display()
+    );
+    assert_eq!(svg.matches("<g ").count(), 4);
+}
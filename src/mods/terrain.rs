@@ -0,0 +1,178 @@
+use std::{fmt, str::FromStr};
+
+use serde_derive::{Deserialize, Serialize};
+
+use super::pathfinding::Distance;
+
+/// The kind of ground covering a cell, which determines the cost of moving into it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Terrain {
+    Road,
+    Grass,
+    Swamp,
+    Forest,
+    Mountain,
+    Water,
+    Wall,
+}
+
+impl Terrain {
+    /// Every terrain, in the order their costs are stored.
+    pub const ALL: [Terrain; 7] = [
+        Terrain::Road,
+        Terrain::Grass,
+        Terrain::Swamp,
+        Terrain::Forest,
+        Terrain::Mountain,
+        Terrain::Water,
+        Terrain::Wall,
+    ];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+impl Default for Terrain {
+    fn default() -> Self {
+        Terrain::Grass
+    }
+}
+
+impl FromStr for Terrain {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        Terrain::ALL
+            .iter()
+            .find(|terrain| terrain.to_string() == s)
+            .cloned()
+            .ok_or_else(|| {
+                format!(
+                    "unknown terrain {:?}, expected road, grass, swamp, forest, mountain, water or wall",
+                    s
+                )
+            })
+    }
+}
+
+impl fmt::Display for Terrain {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use self::Terrain::*;
+        write!(
+            f,
+            "{}",
+            match self {
+                Road => "road",
+                Grass => "grass",
+                Swamp => "swamp",
+                Forest => "forest",
+                Mountain => "mountain",
+                Water => "water",
+                Wall => "wall",
+            }
+        )
+    }
+}
+
+/// How much it costs to move into each type of terrain, as a multiple of the
+/// topology's base step cost, or `None` if the terrain can't be entered.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TerrainCosts {
+    costs: [Option<Distance>; 7],
+    /// Whether diagonal steps may cut the corners of impassable cells.
+    cut_corners: bool,
+}
+
+impl TerrainCosts {
+    /// The cost of moving into `terrain`, or `None` if it's impassable.
+    pub fn get(&self, terrain: Terrain) -> Option<Distance> {
+        self.costs[terrain.index()]
+    }
+
+    /// Sets the cost of moving into `terrain`. Costs must be at least 1.
+    pub fn set(&mut self, terrain: Terrain, cost: Option<Distance>) {
+        assert!(cost != Some(0), "terrain costs must be at least 1");
+        self.costs[terrain.index()] = cost;
+    }
+
+    /// Whether `terrain` can be entered at all.
+    pub fn passable(&self, terrain: Terrain) -> bool {
+        self.get(terrain).is_some()
+    }
+
+    /// The lowest cost of any passable terrain, which the heuristic is scaled
+    /// by so that it never overestimates.
+    pub fn min_cost(&self) -> Distance {
+        self.costs
+            .iter()
+            .filter_map(|cost| *cost)
+            .min()
+            .unwrap_or(1)
+    }
+
+    /// Whether a diagonal step may squeeze past an impassable cell, instead of
+    /// needing both of the cells beside it to be passable too.
+    pub fn cut_corners(&self) -> bool {
+        self.cut_corners
+    }
+
+    pub fn set_cut_corners(&mut self, cut_corners: bool) {
+        self.cut_corners = cut_corners;
+    }
+
+    /// The rules used by the Moving AI benchmark maps: open ground and swamps
+    /// cost the same, trees and water can't be entered, and diagonal steps
+    /// can't cut their corners.
+    pub fn moving_ai() -> Self {
+        let mut costs = Self {
+            costs: [None; 7],
+            cut_corners: false,
+        };
+        costs.set(Terrain::Road, Some(1));
+        costs.set(Terrain::Grass, Some(1));
+        costs.set(Terrain::Swamp, Some(1));
+        costs
+    }
+}
+
+impl Default for TerrainCosts {
+    fn default() -> Self {
+        let mut costs = Self {
+            costs: [None; 7],
+            cut_corners: true,
+        };
+        costs.set(Terrain::Road, Some(1));
+        costs.set(Terrain::Grass, Some(2));
+        costs.set(Terrain::Swamp, Some(3));
+        costs.set(Terrain::Forest, Some(4));
+        costs.set(Terrain::Mountain, Some(8));
+        costs
+    }
+}
+
+#[test]
+fn test_costs() {
+    let mut costs = TerrainCosts::default();
+    assert_eq!(costs.min_cost(), 1);
+    assert!(costs.get(Terrain::Road) < costs.get(Terrain::Grass));
+    assert!(costs.get(Terrain::Forest) < costs.get(Terrain::Mountain));
+    assert!(!costs.passable(Terrain::Water));
+    assert!(!costs.passable(Terrain::Wall));
+
+    costs.set(Terrain::Road, None);
+    costs.set(Terrain::Water, Some(16));
+    assert!(!costs.passable(Terrain::Road));
+    assert_eq!(costs.min_cost(), 2);
+
+    assert!(costs.cut_corners());
+    assert!(!TerrainCosts::moving_ai().cut_corners());
+}
+
+#[test]
+fn test_names_round_trip() {
+    for terrain in Terrain::ALL.iter() {
+        assert_eq!(terrain.to_string().parse::<Terrain>(), Ok(*terrain));
+    }
+    assert!("lava".parse::<Terrain>().is_err());
+}
@@ -0,0 +1,320 @@
+use std::{fmt, str::FromStr};
+
+use serde_derive::{Deserialize, Serialize};
+
+use super::pathfinding::{Distance, Position};
+
+/// The shape of a grid: which cells are adjacent, how far apart cells are,
+/// and where they're drawn.
+pub trait Topology: fmt::Debug {
+    /// The cells adjacent to `position` that are within a grid of the given size.
+    fn neighbours(&self, position: Position, size: Position) -> Vec<Position>;
+
+    /// The base cost of a step between two adjacent cells, before terrain costs are applied.
+    fn step_cost(&self, from: Position, to: Position) -> Distance;
+
+    /// A lower bound on the base cost of getting from `a` to `b`.
+    fn heuristic(&self, a: Position, b: Position) -> Distance;
+
+    /// The cells a step between two adjacent cells squeezes past, which must
+    /// also be passable for the step to be allowed on maps that don't let
+    /// diagonal steps cut corners.
+    fn corners(&self, _from: Position, _to: Position) -> Vec<Position> {
+        Vec::new()
+    }
+
+    /// The screen-space centre of a cell, where `scale` is the distance
+    /// between the centres of orthogonally adjacent cells.
+    fn cell_center(&self, position: Position, scale: f64) -> (f64, f64);
+
+    /// The screen-space corners of a cell, as a closed loop.
+    fn cell_outline(&self, position: Position, scale: f64) -> Vec<(f64, f64)>;
+
+    /// The screen-space size needed to draw a grid of the given size.
+    fn screen_size(&self, size: Position, scale: f64) -> (f64, f64);
+}
+
+/// The base cost of a step between cells that share an edge.
+pub const STEP_COST: Distance = 10;
+/// The base cost of a step between cells that only share a corner, approximating √2 × [STEP_COST].
+pub const DIAGONAL_STEP_COST: Distance = 14;
+
+/// Selects one of the supported topologies.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Geometry {
+    /// A square grid with 4-way movement.
+    Square4,
+    /// A square grid with 8-way movement.
+    Square8,
+    /// A hexagonal grid using axial coordinates.
+    Hex,
+}
+
+impl Geometry {
+    /// Every geometry, in the order the demo cycles through them.
+    pub const ALL: [Geometry; 3] = [Geometry::Square8, Geometry::Square4, Geometry::Hex];
+
+    /// The implementation of this geometry.
+    pub fn topology(self) -> &'static dyn Topology {
+        match self {
+            Geometry::Square4 => &Square4,
+            Geometry::Square8 => &Square8,
+            Geometry::Hex => &Hex,
+        }
+    }
+}
+
+impl FromStr for Geometry {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "square4" => Ok(Geometry::Square4),
+            "square8" => Ok(Geometry::Square8),
+            "hex" => Ok(Geometry::Hex),
+            _ => Err(format!(
+                "unknown grid {:?}, expected square4, square8 or hex",
+                s
+            )),
+        }
+    }
+}
+
+impl Default for Geometry {
+    fn default() -> Self {
+        Geometry::Square8
+    }
+}
+
+impl fmt::Display for Geometry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                Geometry::Square4 => "4-way square grid",
+                Geometry::Square8 => "8-way square grid",
+                Geometry::Hex => "hex grid",
+            }
+        )
+    }
+}
+
+/// Applies each of `offsets` to `position`, keeping the results that are in bounds.
+fn offset_neighbours(
+    position: Position,
+    size: Position,
+    offsets: &[(isize, isize)],
+) -> Vec<Position> {
+    let (x, y) = (position.0 as isize, position.1 as isize);
+    let (width, height) = (size.0 as isize, size.1 as isize);
+    offsets
+        .iter()
+        .map(|(dx, dy)| (x + dx, y + dy))
+        .filter(|(x, y)| *x >= 0 && *y >= 0 && *x < width && *y < height)
+        .map(|(x, y)| (x as Distance, y as Distance))
+        .collect()
+}
+
+fn axis_distances(a: Position, b: Position) -> (Distance, Distance) {
+    (a.0.max(b.0) - a.0.min(b.0), a.1.max(b.1) - a.1.min(b.1))
+}
+
+fn square_center(position: Position, scale: f64) -> (f64, f64) {
+    (
+        (position.0 as f64 + 0.5) * scale,
+        (position.1 as f64 + 0.5) * scale,
+    )
+}
+
+fn square_outline(position: Position, scale: f64) -> Vec<(f64, f64)> {
+    let (x, y) = square_center(position, scale);
+    let r = scale / 2.;
+    vec![
+        (x - r, y - r),
+        (x + r, y - r),
+        (x + r, y + r),
+        (x - r, y + r),
+        (x - r, y - r),
+    ]
+}
+
+/// A square grid where each cell is adjacent to the four cells sharing its edges.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Square4;
+
+impl Topology for Square4 {
+    fn neighbours(&self, position: Position, size: Position) -> Vec<Position> {
+        offset_neighbours(position, size, &[(0, -1), (-1, 0), (1, 0), (0, 1)])
+    }
+
+    fn step_cost(&self, _from: Position, _to: Position) -> Distance {
+        STEP_COST
+    }
+
+    /// Manhattan distance.
+    fn heuristic(&self, a: Position, b: Position) -> Distance {
+        let (dx, dy) = axis_distances(a, b);
+        (dx + dy) * STEP_COST
+    }
+
+    fn cell_center(&self, position: Position, scale: f64) -> (f64, f64) {
+        square_center(position, scale)
+    }
+
+    fn cell_outline(&self, position: Position, scale: f64) -> Vec<(f64, f64)> {
+        square_outline(position, scale)
+    }
+
+    fn screen_size(&self, size: Position, scale: f64) -> (f64, f64) {
+        (size.0 as f64 * scale, size.1 as f64 * scale)
+    }
+}
+
+/// A square grid where each cell is also adjacent to the four cells sharing its corners.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Square8;
+
+impl Topology for Square8 {
+    fn neighbours(&self, position: Position, size: Position) -> Vec<Position> {
+        offset_neighbours(
+            position,
+            size,
+            &[
+                (-1, -1),
+                (0, -1),
+                (1, -1),
+                (-1, 0),
+                (1, 0),
+                (-1, 1),
+                (0, 1),
+                (1, 1),
+            ],
+        )
+    }
+
+    fn step_cost(&self, from: Position, to: Position) -> Distance {
+        if from.0 != to.0 && from.1 != to.1 {
+            DIAGONAL_STEP_COST
+        } else {
+            STEP_COST
+        }
+    }
+
+    /// Diagonal steps squeeze past the two cells beside both of their ends.
+    fn corners(&self, from: Position, to: Position) -> Vec<Position> {
+        if from.0 != to.0 && from.1 != to.1 {
+            vec![(from.0, to.1), (to.0, from.1)]
+        } else {
+            Vec::new()
+        }
+    }
+
+    /// Octile distance: as many diagonal steps as possible, then straight ones.
+    fn heuristic(&self, a: Position, b: Position) -> Distance {
+        let (dx, dy) = axis_distances(a, b);
+        let diagonal = dx.min(dy);
+        let straight = dx.max(dy) - diagonal;
+        diagonal * DIAGONAL_STEP_COST + straight * STEP_COST
+    }
+
+    fn cell_center(&self, position: Position, scale: f64) -> (f64, f64) {
+        square_center(position, scale)
+    }
+
+    fn cell_outline(&self, position: Position, scale: f64) -> Vec<(f64, f64)> {
+        square_outline(position, scale)
+    }
+
+    fn screen_size(&self, size: Position, scale: f64) -> (f64, f64) {
+        (size.0 as f64 * scale, size.1 as f64 * scale)
+    }
+}
+
+/// A grid of pointy-topped hexagons in axial coordinates, with `x` as the `q`
+/// axis and `y` as the `r` axis, so the grid is drawn as a rhombus.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Hex;
+
+impl Hex {
+    /// The height of a row of hexes, relative to the distance between centres.
+    const ROW_HEIGHT: f64 = 0.866_025_403_784_438_6;
+}
+
+impl Topology for Hex {
+    fn neighbours(&self, position: Position, size: Position) -> Vec<Position> {
+        offset_neighbours(
+            position,
+            size,
+            &[(0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1)],
+        )
+    }
+
+    fn step_cost(&self, _from: Position, _to: Position) -> Distance {
+        STEP_COST
+    }
+
+    /// Hex distance, which is half the Manhattan distance in cube coordinates.
+    fn heuristic(&self, a: Position, b: Position) -> Distance {
+        let dq = a.0 as isize - b.0 as isize;
+        let dr = a.1 as isize - b.1 as isize;
+        ((dq.abs() + dr.abs() + (dq + dr).abs()) / 2) as Distance * STEP_COST
+    }
+
+    fn cell_center(&self, position: Position, scale: f64) -> (f64, f64) {
+        let (q, r) = (position.0 as f64, position.1 as f64);
+        (
+            (q + r / 2. + 0.5) * scale,
+            (r * Self::ROW_HEIGHT + 0.5) * scale,
+        )
+    }
+
+    fn cell_outline(&self, position: Position, scale: f64) -> Vec<(f64, f64)> {
+        let (x, y) = self.cell_center(position, scale);
+        let radius = scale / (2. * Self::ROW_HEIGHT);
+        (0..=6)
+            .map(|i| {
+                let angle = std::f64::consts::PI / 3. * (i as f64 + 0.5);
+                (x + radius * angle.cos(), y + radius * angle.sin())
+            })
+            .collect()
+    }
+
+    fn screen_size(&self, size: Position, scale: f64) -> (f64, f64) {
+        let (width, height) = (size.0 as f64, size.1 as f64);
+        (
+            (width + (height - 1.) / 2.) * scale,
+            ((height - 1.) * Self::ROW_HEIGHT + 1.) * scale,
+        )
+    }
+}
+
+#[test]
+fn test_neighbours_are_symmetric_and_one_step_away() {
+    let size = (5, 4);
+    for geometry in Geometry::ALL.iter() {
+        let topology = geometry.topology();
+        for x in 0..size.0 {
+            for y in 0..size.1 {
+                for neighbour in topology.neighbours((x, y), size) {
+                    assert_ne!(neighbour, (x, y));
+                    assert_eq!(
+                        topology.heuristic((x, y), neighbour),
+                        topology.step_cost((x, y), neighbour)
+                    );
+                    assert!(topology.neighbours(neighbour, size).contains(&(x, y)));
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_hex_neighbours_are_equidistant_on_screen() {
+    let center = Hex.cell_center((2, 2), 1.);
+    for neighbour in Hex.neighbours((2, 2), (5, 5)) {
+        let (x, y) = Hex.cell_center(neighbour, 1.);
+        let distance = ((x - center.0).powi(2) + (y - center.1).powi(2)).sqrt();
+        assert!((distance - 1.).abs() < 1e-9);
+    }
+}
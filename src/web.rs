@@ -3,37 +3,21 @@
 #![warn(missing_docs)]
 
 mod mods;
-use self::mods::pathfinding;
-
-use serde_derive::Serialize;
-use serdebug::SerDebug;
-
-use std::convert::TryFrom;
+use self::mods::output::{Output, OutputCircle, OutputLine};
+use self::mods::{
+    anyangle, components, cooperative, drawing, dstar, flowfield, generators, map, pathfinding,
+    renderbuffer, topology,
+};
 
 #[allow(unused_imports)]
 use log::{debug, error, info, log, trace, warn, Log};
 
 use js_sys;
 use rand::prng::chacha::ChaChaCore;
-use rand::SeedableRng;
+use rand::{Rng, SeedableRng};
 use rand_core::block::BlockRng;
 use wasm_bindgen::prelude::{wasm_bindgen, JsValue};
 
-#[derive(Serialize, SerDebug)]
-struct Output {
-    timeout: u32,
-    width: u32,
-    height: u32,
-    lines: Vec<OutputLine>,
-}
-
-#[derive(Serialize, SerDebug)]
-struct OutputLine {
-    color: &'static str,
-    width: f64,
-    points: Vec<(f64, f64)>,
-}
-
 #[wasm_bindgen]
 extern "C" {
     #[wasm_bindgen(js_name=setTitle)]
@@ -50,13 +34,80 @@ extern "C" {
 #[wasm_bindgen]
 pub struct Application {
     render_scale: u32,
-    width: u32,
-    height: u32,
 
     demo_iteration: usize,
     rng: BlockRng<ChaChaCore>,
 
-    pathfinders: Vec<pathfinding::AStarPathfinder>,
+    demos: Vec<Demo>,
+    path_mode: PathMode,
+    /// The last any-angle path found for a finished search, along with the
+    /// mode and endpoints it was found for, so that it's only searched for
+    /// again once one of them or the map changes.
+    any_angle_path: Option<(
+        (PathMode, pathfinding::Position, pathfinding::Position),
+        Vec<pathfinding::Position>,
+    )>,
+    /// Whether the map is being edited, which holds the demos where they are.
+    editing: bool,
+    /// The latest frame, where the canvas reads it from.
+    frame: renderbuffer::RenderBuffer,
+}
+
+/// How the paths found by finished searches are drawn.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PathMode {
+    /// From cell to cell, as found.
+    Cells,
+    /// Pulled taut through the cells that can see each other.
+    Smoothed,
+    /// Replaced by a Theta* search between the same points.
+    Theta,
+    /// Replaced by a Lazy Theta* search between the same points.
+    LazyTheta,
+}
+
+impl std::fmt::Display for PathMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}",
+            match self {
+                PathMode::Cells => "cell by cell",
+                PathMode::Smoothed => "string-pulled",
+                PathMode::Theta => "any-angle with Theta*",
+                PathMode::LazyTheta => "any-angle with Lazy Theta*",
+            }
+        )
+    }
+}
+
+/// One of the demos that the application cycles through.
+enum Demo {
+    /// A single search, animated as it explores.
+    Search(pathfinding::AStarPathfinder),
+    /// An agent walking to its goal as walls appear in its way, repairing its
+    /// path with D* Lite each time.
+    Replanning {
+        dstar: dstar::DStarLite,
+        /// The cells the agent has walked through so far.
+        trail: Vec<pathfinding::Position>,
+    },
+    /// A crowd of units all following the same flow field to the nearest exit.
+    Flow {
+        map: map::Map,
+        field: flowfield::FlowField,
+        /// Where each unit currently is.
+        units: Vec<pathfinding::Position>,
+    },
+    /// Agents crossing the map together without colliding, stepping along
+    /// their timed paths from cooperative A*.
+    Agents {
+        map: map::Map,
+        /// Each agent's position at each step.
+        paths: Vec<Vec<pathfinding::Position>>,
+        /// The current step.
+        time: cooperative::Time,
+    },
 }
 
 static LOGGER: &'static (dyn log::Log + 'static) = &WebConsoleLogger;
@@ -83,101 +134,543 @@ impl Application {
         debug!("Seeding RNG with {:?} from timestamp {}.", seed, timestamp);
         let rng = BlockRng::new(ChaChaCore::from_seed(seed));
 
-        let width = 64;
-        let height = 64;
-
         Application {
             rng,
             demo_iteration: 0,
-            width: u32::try_from(width).unwrap(),
-            height: u32::try_from(height).unwrap(),
             render_scale: 32,
-            pathfinders: vec![pathfinding::AStarPathfinder::demo(0)],
+            demos: vec![demo(0)],
+            path_mode: PathMode::Cells,
+            any_angle_path: None,
+            editing: false,
+            frame: renderbuffer::RenderBuffer::new(),
         }
     }
 
-    pub fn tick(&mut self) -> JsValue {
-        let width = self.width * self.render_scale;
-        let height = self.height * self.render_scale;
-        let mut any_working = false;
+    /// Holds the current demo's map still for editing, replacing the demo
+    /// with a search across it if it wasn't one already.
+    pub fn start_editing(&mut self) {
+        let pathfinder = self.edited_search();
+        resolve(pathfinder);
+        set_text(&format!(
+            "editing {} on a {}: drag to paint walls, shift-drag to clear them, \
+             drag either end to move it, and press escape to carry on",
+            pathfinder.strategy(),
+            pathfinder.map().geometry()
+        ));
+    }
 
-        let scale = self.render_scale;
-        let scale_f64 = scale as f64;
-        let scale_point = |(x, y): &(usize, usize)| -> (f64, f64) {
-            let xp = ((*x as u32) * scale + (scale / 2)) as f64;
-            let yp = ((*y as u32) * scale + (scale / 2)) as f64;
-            (xp, yp)
+    /// Lets the demos carry on from the map being edited.
+    pub fn stop_editing(&mut self) {
+        self.editing = false;
+    }
+
+    /// Changes the terrain of a cell to the one with the given name, such as
+    /// "wall" or "grass", and solves the map again. Returns whether the cell
+    /// and terrain were valid.
+    pub fn set_cell(&mut self, x: u32, y: u32, terrain: &str) -> bool {
+        let terrain = match terrain.parse() {
+            Ok(terrain) => terrain,
+            Err(error) => {
+                warn!("{}", error);
+                return false;
+            }
         };
+        let position = (x as pathfinding::Distance, y as pathfinding::Distance);
+        let pathfinder = self.edited_search();
+        if !pathfinder.map().contains(position) {
+            return false;
+        }
+        if pathfinder.map().terrain()[position] != terrain {
+            pathfinder.map_mut().set_terrain(position, terrain);
+            resolve(pathfinder);
+            self.any_angle_path = None;
+        }
+        true
+    }
 
-        let mut lines = vec![];
+    /// Moves the start of the path and solves the map again. Returns whether
+    /// the cell was on the map.
+    pub fn set_origin(&mut self, x: u32, y: u32) -> bool {
+        let position = (x as pathfinding::Distance, y as pathfinding::Distance);
+        let pathfinder = self.edited_search();
+        if !pathfinder.map().contains(position) {
+            return false;
+        }
+        let target = pathfinder.targets()[0];
+        pathfinder.reset(position, target);
+        resolve(pathfinder);
+        true
+    }
 
-        for (i, pathfinder) in self.pathfinders.iter_mut().enumerate() {
-            if pathfinder.working() {
-                any_working = true;
+    /// Moves the end of the path and solves the map again. Returns whether the
+    /// cell was on the map.
+    pub fn set_target(&mut self, x: u32, y: u32) -> bool {
+        let position = (x as pathfinding::Distance, y as pathfinding::Distance);
+        let pathfinder = self.edited_search();
+        if !pathfinder.map().contains(position) {
+            return false;
+        }
+        let origin = pathfinder.origin();
+        pathfinder.reset(origin, position);
+        resolve(pathfinder);
+        true
+    }
 
-                pathfinder.get_path();
+    /// The start and end of the path being edited, as `[[x, y], [x, y]]`, or
+    /// null if we're not editing.
+    pub fn endpoints(&self) -> JsValue {
+        let endpoints = match (self.editing, self.demos.first()) {
+            (true, Some(Demo::Search(pathfinder))) => {
+                Some((pathfinder.origin(), pathfinder.targets()[0]))
+            }
+            _ => None,
+        };
+        JsValue::from_serde(&endpoints).unwrap()
+    }
 
-                // for _ in 0..128 {
-                //     pathfinder.step();
-                // }
+    /// The cell drawn at a point on the canvas, as `[x, y]`, or null if
+    /// there's no cell there or the point isn't a number.
+    pub fn canvas_to_cell(&self, x: f64, y: f64) -> JsValue {
+        if !x.is_finite() || !y.is_finite() {
+            return JsValue::NULL;
+        }
+        let scale = f64::from(self.render_scale);
+        let cell = self.demos.first().and_then(|demo| {
+            let map = demo_map(demo);
+            let topology = map.topology();
+            let distance = |position: &pathfinding::Position| {
+                let (xp, yp) = topology.cell_center(*position, scale);
+                (xp - x).powi(2) + (yp - y).powi(2)
+            };
+            // Every point is closest to the centre of the cell it's in, as long
+            // as it's in one at all.
+            map.terrain()
+                .iter()
+                .map(|(position, _)| position)
+                .min_by(|a, b| distance(a).partial_cmp(&distance(b)).unwrap())
+                .filter(|position| distance(position) <= scale * scale / 2.)
+        });
+        JsValue::from_serde(&cell).unwrap()
+    }
+
+    /// Chooses how the paths found by finished searches are drawn: "cells",
+    /// "smoothed", "theta" or "lazy-theta". Returns whether the mode was known.
+    pub fn set_path_mode(&mut self, mode: &str) -> bool {
+        self.path_mode = match mode {
+            "cells" => PathMode::Cells,
+            "smoothed" => PathMode::Smoothed,
+            "theta" => PathMode::Theta,
+            "lazy-theta" => PathMode::LazyTheta,
+            _ => return false,
+        };
+        info!("Drawing finished paths {}.", self.path_mode);
+        true
+    }
 
-                if !pathfinder.working() {
-                    any_working = false;
+    /// Advances the demos and draws the next frame into the frame buffer,
+    /// returning the number of milliseconds to wait before the next tick, or
+    /// 0 to tick again on the next animation frame.
+    pub fn tick(&mut self) -> u32 {
+        let mut output = Output::new(&drawing::LAYERS);
+        let mut any_working = false;
+
+        let scale = f64::from(self.render_scale);
+
+        for demo in self.demos.iter_mut() {
+            let finished = match demo {
+                Demo::Search(pathfinder) => {
+                    // Instant isn't available on the web, so each frame gets a fixed number of steps.
+                    if pathfinder.step_n(32) == pathfinding::SearchProgress::InProgress {
+                        any_working = true;
+                    }
+
+                    let map = pathfinder.map();
+                    drawing::draw_terrain(map, scale, &mut output);
+                    drawing::draw_search(
+                        map,
+                        pathfinder.search(),
+                        drawing::FORWARD_SEARCH_COLORS,
+                        scale,
+                        &mut output,
+                    );
+                    // The search from the target, if there is one, is drawn in blue.
+                    if let Some(search) = pathfinder.backward_search() {
+                        drawing::draw_search(
+                            map,
+                            search,
+                            drawing::BACKWARD_SEARCH_COLORS,
+                            scale,
+                            &mut output,
+                        );
+                    }
+
+                    let result = pathfinder.peek_path();
+                    if result.found() && self.path_mode != PathMode::Cells {
+                        let path = match self.path_mode {
+                            PathMode::Cells => result.path,
+                            PathMode::Smoothed => anyangle::smooth_path(map, &result.path),
+                            PathMode::Theta | PathMode::LazyTheta => {
+                                let key = (
+                                    self.path_mode,
+                                    pathfinder.origin(),
+                                    result.path[result.path.len() - 1],
+                                );
+                                match &self.any_angle_path {
+                                    Some((cached, path)) if *cached == key => path.clone(),
+                                    _ => {
+                                        let lazy = self.path_mode == PathMode::LazyTheta;
+                                        let path = anyangle::AnyAngleSearch::new(map.clone(), lazy)
+                                            .find_path(key.1, key.2)
+                                            .path;
+                                        self.any_angle_path = Some((key, path.clone()));
+                                        path
+                                    }
+                                }
+                            }
+                        };
+                        set_text(&format!(
+                            "{}{} on a {}, drawn {}",
+                            if pathfinder.bidirectional() {
+                                "bidirectional "
+                            } else {
+                                ""
+                            },
+                            pathfinder.strategy(),
+                            map.geometry(),
+                            self.path_mode
+                        ));
+                        drawing::draw_path(map, &path, scale, &mut output);
+                    } else {
+                        drawing::draw_path(map, &result.path, scale, &mut output);
+                    }
+
+                    drawing::draw_label(map, pathfinder.origin(), "S", scale, &mut output);
+                    for target in pathfinder.targets() {
+                        drawing::draw_label(map, *target, "G", scale, &mut output);
+                    }
+
+                    !pathfinder.working()
                 }
-            }
 
-            for ((x, y), info) in pathfinder.data().iter() {
-                use self::mods::pathfinding::AStarCellState::*;
-                let (xp, yp) = scale_point(&(x, y));
-                match info.state() {
-                    Blocked => {
-                        lines.push(OutputLine {
-                            color: "rgba(192, 0, 64, 1.0)",
-                            width: 0.3 * scale_f64,
-                            points: vec![
-                                (xp - scale_f64 / 3., yp - scale_f64 / 3.),
-                                (xp + scale_f64 / 3., yp - scale_f64 / 3.),
-                                (xp + scale_f64 / 3., yp + scale_f64 / 3.),
-                                (xp - scale_f64 / 3., yp + scale_f64 / 3.),
-                                (xp - scale_f64 / 3., yp - scale_f64 / 3.),
-                            ],
+                Demo::Replanning { dstar, trail } => {
+                    // Now and then, a wall appears somewhere ahead of the agent.
+                    let planned = dstar.path();
+                    if planned.path.len() > 3 && self.rng.gen_range(0, 3) == 0 {
+                        let ahead = self.rng.gen_range(2, planned.path.len() - 1);
+                        dstar.set_blocked(planned.path[ahead]);
+                    }
+
+                    let repaired = dstar.path();
+                    set_text(&format!(
+                        "D* Lite on a {}, expanding {} cells to repair the path",
+                        dstar.map().geometry(),
+                        repaired.nodes_expanded
+                    ));
+
+                    let map = dstar.map();
+                    let topology = map.topology();
+                    drawing::draw_terrain(map, scale, &mut output);
+                    output.layer("search").lines.push(OutputLine {
+                        color: "rgba(192, 192, 64, 1.0)",
+                        width: 0.25 * scale,
+                        points: trail
+                            .iter()
+                            .map(|position| topology.cell_center(*position, scale))
+                            .collect(),
+                    });
+                    drawing::draw_path(map, &repaired.path, scale, &mut output);
+                    drawing::draw_label(map, dstar.goal(), "G", scale, &mut output);
+
+                    if repaired.found() && repaired.path.len() > 1 {
+                        any_working = true;
+                        dstar.move_agent(repaired.path[1]);
+                        trail.push(repaired.path[1]);
+                        false
+                    } else {
+                        true
+                    }
+                }
+
+                Demo::Flow { map, field, units } => {
+                    let topology = map.topology();
+                    drawing::draw_terrain(map, scale, &mut output);
+
+                    let layer = output.layer("search");
+                    for (position, direction) in field.directions().iter() {
+                        if *direction != flowfield::Direction::None {
+                            layer.lines.push(OutputLine {
+                                color: "rgba(224, 224, 224, 0.5)",
+                                width: 0.0625 * scale,
+                                points: arrow(
+                                    topology.cell_center(position, scale),
+                                    topology.cell_center(direction.step(position), scale),
+                                ),
+                            });
+                        }
+                    }
+
+                    let mut moved = false;
+                    let layer = output.layer("paths");
+                    for unit in units.iter_mut() {
+                        if let Some(next) = field.next_step(*unit) {
+                            *unit = next;
+                            moved = true;
+                        }
+                        let (x, y) = topology.cell_center(*unit, scale);
+                        layer.circles.push(OutputCircle {
+                            color: "rgba(64, 192, 64, 1.0)",
+                            x,
+                            y,
+                            radius: 0.375 * scale,
                         });
                     }
-                    VisitedFrom(position) => {
-                        lines.push(OutputLine {
-                            color: "rgba(192, 192, 64, 1.0)",
-                            width: 0.125 * (scale as f64),
-                            points: vec![scale_point(&position), (xp, yp)],
+
+                    if moved {
+                        any_working = true;
+                    }
+                    !moved
+                }
+
+                Demo::Agents { map, paths, time } => {
+                    let topology = map.topology();
+                    drawing::draw_terrain(map, scale, &mut output);
+
+                    let layer = output.layer("paths");
+                    for (i, path) in paths.iter().enumerate() {
+                        let color = AGENT_COLORS[i % AGENT_COLORS.len()];
+                        let now = (*time).min(path.len() - 1);
+                        layer.lines.push(OutputLine {
+                            color,
+                            width: 0.125 * scale,
+                            points: path[now..]
+                                .iter()
+                                .map(|position| topology.cell_center(*position, scale))
+                                .collect(),
+                        });
+                        let (x, y) = topology.cell_center(path[now], scale);
+                        layer.circles.push(OutputCircle {
+                            color,
+                            x,
+                            y,
+                            radius: 0.5 * scale,
                         });
                     }
-                    Free => {}
+
+                    *time += 1;
+                    if paths.iter().any(|path| path.len() > *time) {
+                        any_working = true;
+                        false
+                    } else {
+                        true
+                    }
                 }
-            }
+            };
 
-            lines.push(OutputLine {
-                color: "rgba(64, 192, 64, 1.0)",
-                width: 0.5 * (scale as f64),
-                points: pathfinder
-                    .peek_path()
-                    .unwrap()
-                    .iter()
-                    .map(scale_point)
-                    .collect(),
-            });
-
-            if !pathfinder.working() {
+            let map = demo_map(demo);
+            let (width, height) = map.topology().screen_size(map.len(), scale);
+            output.width = output.width.max(width.ceil() as u32);
+            output.height = output.height.max(height.ceil() as u32);
+
+            if finished && !self.editing {
                 self.demo_iteration += 1;
-                *pathfinder = pathfinding::AStarPathfinder::demo(self.demo_iteration);
+                *demo = self::demo(self.demo_iteration);
+                self.any_angle_path = None;
             }
         }
 
-        JsValue::from_serde(&Output {
-            timeout: if any_working { 250 } else { 250 },
-            width,
-            height,
-            lines,
-        }).unwrap()
+        output.timeout = match (any_working, self.demos.first()) {
+            (true, Some(Demo::Replanning { .. }))
+            | (true, Some(Demo::Flow { .. }))
+            | (true, Some(Demo::Agents { .. })) => 100,
+            (true, _) => 0,
+            (false, _) if self.editing => 50,
+            (false, _) => 1000,
+        };
+        self.frame.encode(&output);
+        output.timeout
     }
+
+    /// Where the latest frame starts in the module's memory, as `f32`s laid
+    /// out as described in the `renderbuffer` module. It moves whenever the
+    /// frame grows, so it must be read again after each tick.
+    pub fn frame_ptr(&self) -> *const f32 {
+        self.frame.data().as_ptr()
+    }
+
+    /// The number of `f32`s in the latest frame.
+    pub fn frame_len(&self) -> usize {
+        self.frame.data().len()
+    }
+
+    /// Sends every cell again in the next frame, such as when the canvas has
+    /// been cleared.
+    pub fn redraw(&mut self) {
+        self.frame.reset();
+    }
+}
+
+impl Application {
+    /// The search across the map being edited, which stops the demos moving on.
+    fn edited_search(&mut self) -> &mut pathfinding::AStarPathfinder {
+        self.editing = true;
+        let demo = &mut self.demos[0];
+        let ends = match demo {
+            Demo::Search(_) => None,
+            Demo::Replanning { dstar, .. } => Some((dstar.start(), dstar.goal())),
+            Demo::Flow { .. } | Demo::Agents { .. } => Some(((1, 1), (62, 62))),
+        };
+        if let Some((origin, target)) = ends {
+            let map = demo_map(demo).clone();
+            *demo = Demo::Search(pathfinding::AStarPathfinder::new(
+                map,
+                origin,
+                target,
+                Default::default(),
+            ));
+        }
+        match demo {
+            Demo::Search(pathfinder) => pathfinder,
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// The map a demo is drawn over.
+fn demo_map(demo: &Demo) -> &map::Map {
+    match demo {
+        Demo::Search(pathfinder) => pathfinder.map(),
+        Demo::Replanning { dstar, .. } => dstar.map(),
+        Demo::Flow { map, .. } | Demo::Agents { map, .. } => map,
+    }
+}
+
+/// Solves an edited map straight away, relabelling its components first so
+/// that ends which have been walled off from each other are found at once.
+fn resolve(pathfinder: &mut pathfinding::AStarPathfinder) {
+    let components = components::Components::new(pathfinder.map());
+    pathfinder.set_components(Some(components));
+    pathfinder.get_path();
+}
+
+/// The colours that agents moving together are told apart by.
+const AGENT_COLORS: [&str; 4] = [
+    "rgba(64, 192, 64, 1.0)",
+    "rgba(64, 160, 192, 1.0)",
+    "rgba(224, 224, 224, 1.0)",
+    "rgba(192, 64, 192, 1.0)",
+];
+
+/// The points of an arrow pointing from one cell centre toward the next, drawn
+/// as a single line doubling back over itself to draw the head.
+fn arrow(from: (f64, f64), to: (f64, f64)) -> Vec<(f64, f64)> {
+    let (dx, dy) = ((to.0 - from.0) * 0.3, (to.1 - from.1) * 0.3);
+    let tail = (from.0 - dx, from.1 - dy);
+    let tip = (from.0 + dx, from.1 + dy);
+    let head = |side: f64| {
+        (
+            tip.0 - dx * 0.5 - dy * 0.5 * side,
+            tip.1 - dy * 0.5 + dx * 0.5 * side,
+        )
+    };
+    vec![tail, tip, head(1.), tip, head(-1.)]
+}
+
+/// Constructs the demo for a demo iteration. Each demo map is solved with
+/// every search strategy and then A* from both ends, on every geometry in turn
+/// so they can be compared, then walked across while D* Lite repairs the path,
+/// emptied of a crowd following a flow field to the nearest corner, and
+/// crossed by agents going both ways along the same roads. Last, a generated
+/// map is solved. The walk and the generated map have a corridor carved
+/// through them if they had no route.
+fn demo(demo_iteration: usize) -> Demo {
+    let strategies = &pathfinding::SearchStrategy::ALL;
+    let geometries = &topology::Geometry::ALL;
+    let searches = (strategies.len() + 1) * geometries.len();
+    let map_iteration = demo_iteration / (searches + 4);
+    let demo_iteration = demo_iteration % (searches + 4);
+
+    if demo_iteration == searches + 3 {
+        let generators = &generators::Generator::ALL;
+        let generator = generators[map_iteration % generators.len()];
+        let (origin, target) = ((1, 1), (61, 61));
+        let map = generator.generate_solvable(
+            64,
+            64,
+            topology::Geometry::Square8,
+            map_iteration as u64,
+            (origin, target),
+            generators::Repair::Carve,
+        );
+        let components = components::Components::new(&map);
+        let mut pathfinder =
+            pathfinding::AStarPathfinder::new(map, origin, target, Default::default());
+        pathfinder.set_components(Some(components));
+        set_text(&format!(
+            "{} across a map of {}",
+            pathfinder.strategy(),
+            generator
+        ));
+        return Demo::Search(pathfinder);
+    }
+
+    if demo_iteration == searches + 2 {
+        let map = map::Map::demo(map_iteration, topology::Geometry::Square8);
+        // Agents start along one north-south road and swap ends with those
+        // along another, so they meet head-on and have to make way.
+        let agents: Vec<(pathfinding::Position, pathfinding::Position)> = (0..5)
+            .flat_map(|i| {
+                let y = 6 + 12 * i;
+                vec![((8, y), (56, 60 - y)), ((56, 60 - y), (8, y))]
+            }).collect();
+        let paths = cooperative::CooperativePlanner::plan(map.clone(), &agents)
+            .into_iter()
+            .zip(&agents)
+            .map(|(path, (origin, _))| path.unwrap_or_else(|| vec![*origin]))
+            .collect();
+        set_text("cooperative A* moving agents without collisions");
+        return Demo::Agents {
+            map,
+            paths,
+            time: 0,
+        };
+    }
+
+    if demo_iteration == searches + 1 {
+        let map = map::Map::demo(map_iteration, topology::Geometry::Square8);
+        let field = flowfield::FlowField::new(&map, &[(1, 1), (62, 62)]);
+        let units = (0..8)
+            .flat_map(|x| (0..8).map(move |y| (x * 8 + 4, y * 8 + 4)))
+            .filter(|position| field.distance(*position).is_some())
+            .collect();
+        set_text("a crowd sharing one flow field to the nearest corner");
+        return Demo::Flow { map, field, units };
+    }
+
+    if demo_iteration == searches {
+        let mut map = map::Map::demo(map_iteration, topology::Geometry::Square8);
+        generators::carve_corridor(&mut map, (1, 1), (62, 62));
+        return Demo::Replanning {
+            dstar: dstar::DStarLite::new(map, (1, 1), (62, 62)),
+            trail: vec![(1, 1)],
+        };
+    }
+
+    let geometry = geometries[demo_iteration / (strategies.len() + 1)];
+    let pathfinder = match strategies.get(demo_iteration % (strategies.len() + 1)) {
+        Some(strategy) => {
+            set_text(&format!("{} on a {}", strategy, geometry));
+            pathfinding::AStarPathfinder::demo(map_iteration, geometry, *strategy)
+        }
+        None => {
+            let strategy = pathfinding::SearchStrategy::AStar;
+            set_text(&format!("bidirectional {} on a {}", strategy, geometry));
+            let mut pathfinder =
+                pathfinding::AStarPathfinder::demo(map_iteration, geometry, strategy);
+            pathfinder.set_bidirectional(true);
+            pathfinder
+        }
+    };
+    Demo::Search(pathfinder)
 }
 
 #[derive(Debug)]